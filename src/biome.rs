@@ -0,0 +1,187 @@
+/****************************************
+   Biomes
+   every column of the world belongs to a biome
+   picked from temperature and humidity noise,
+   the biome decides what the surface is made of
+   and how rough the terrain around it is
+****************************************/
+use macroquad::color::Color;
+
+use crate::{
+    noise::{rand3, sub_seed, Noise},
+    registry::{BlockId, DIRT, GRASS, GRAVEL, SAND, STONE},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(u8)]
+pub enum Biome {
+    #[default]
+    Plains = 0,
+    Desert = 1,
+    GravellyShore = 2,
+    RockyHighlands = 3,
+}
+impl Biome {
+    pub const ALL: [Biome; 4] = [
+        Biome::Plains,
+        Biome::Desert,
+        Biome::GravellyShore,
+        Biome::RockyHighlands,
+    ];
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Biome::Plains => "plains",
+            Biome::Desert => "desert",
+            Biome::GravellyShore => "gravelly shore",
+            Biome::RockyHighlands => "rocky highlands",
+        }
+    }
+    /// the block on top of every column
+    pub fn surface(self) -> BlockId {
+        match self {
+            Biome::Plains => GRASS,
+            Biome::Desert => SAND,
+            Biome::GravellyShore => GRAVEL,
+            Biome::RockyHighlands => STONE,
+        }
+    }
+    /// the blocks between the surface and stone
+    pub fn subsurface(self) -> BlockId {
+        match self {
+            Biome::Plains => DIRT,
+            Biome::Desert => SAND,
+            Biome::GravellyShore => SAND,
+            Biome::RockyHighlands => STONE,
+        }
+    }
+    /// how far above (or below) the world's base height the terrain sits
+    pub fn height_offset(self) -> f32 {
+        match self {
+            Biome::Plains => 0.,
+            Biome::Desert => 2.,
+            Biome::GravellyShore => -6.,
+            Biome::RockyHighlands => 8.,
+        }
+    }
+    /// how far hills go above and below the biome's height
+    pub fn amplitude(self) -> f32 {
+        match self {
+            Biome::Plains => 5.,
+            Biome::Desert => 3.,
+            Biome::GravellyShore => 2.,
+            Biome::RockyHighlands => 14.,
+        }
+    }
+    /// temperature and humidity this biome is the most likely at
+    fn climate(self) -> (f32, f32) {
+        match self {
+            Biome::Plains => (0.0, 0.1),
+            Biome::Desert => (0.6, -0.5),
+            Biome::GravellyShore => (-0.1, 0.7),
+            Biome::RockyHighlands => (-0.6, -0.4),
+        }
+    }
+    /// color tiles of this biome are multiplied with
+    pub fn tint(self) -> Color {
+        match self {
+            Biome::Plains => Color::new(1., 1., 1., 1.),
+            Biome::Desert => Color::new(1., 0.95, 0.82, 1.),
+            Biome::GravellyShore => Color::new(0.88, 0.94, 1., 1.),
+            Biome::RockyHighlands => Color::new(0.86, 0.88, 0.92, 1.),
+        }
+    }
+}
+
+/// decides the biome of every column from a seed
+#[derive(Debug, Clone)]
+pub struct BiomeSource {
+    seed: u64,
+    temperature: Noise,
+    humidity: Noise,
+    /// size of biomes in blocks
+    pub scale: f32,
+    /// how sharp borders between biomes are, higher means narrower transitions
+    pub sharpness: f32,
+}
+impl BiomeSource {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed: sub_seed(seed, 10),
+            temperature: Noise::new(sub_seed(seed, 11)),
+            humidity: Noise::new(sub_seed(seed, 12)),
+            scale: 160.,
+            sharpness: 24.,
+        }
+    }
+    /// temperature and humidity of a column, both roughly in `-1.0..1.0`
+    pub fn climate(&self, x: i32, y: i32) -> (f32, f32) {
+        let (fx, fy) = (x as f32 / self.scale, y as f32 / self.scale);
+        (
+            self.temperature.fbm2(fx, fy, 3) * 1.5,
+            self.humidity.fbm2(fx, fy, 3) * 1.5,
+        )
+    }
+    /// how much every biome (in [`Biome::ALL`] order) contributes to a column, sums up to 1
+    pub fn weights(&self, x: i32, y: i32) -> [f32; Biome::ALL.len()] {
+        let (t, h) = self.climate(x, y);
+        let mut w = Biome::ALL.map(|b| {
+            let (bt, bh) = b.climate();
+            let d = (t - bt).powi(2) + (h - bh).powi(2);
+            (-self.sharpness * d).exp()
+        });
+        let sum: f32 = w.iter().sum();
+        if sum > 0. {
+            w.iter_mut().for_each(|v| *v /= sum);
+        } else {
+            w = [0.; Biome::ALL.len()];
+            w[self.dominant_index(t, h)] = 1.;
+        }
+        w
+    }
+    fn dominant_index(&self, t: f32, h: f32) -> usize {
+        let d = Biome::ALL.map(|b| {
+            let (bt, bh) = b.climate();
+            (t - bt).powi(2) + (h - bh).powi(2)
+        });
+        (0..d.len())
+            .min_by(|a, b| d[*a].total_cmp(&d[*b]))
+            .unwrap_or(0)
+    }
+    /// the biome closest to the climate of a column
+    pub fn dominant(&self, x: i32, y: i32) -> Biome {
+        let (t, h) = self.climate(x, y);
+        Biome::ALL[self.dominant_index(t, h)]
+    }
+    /// biome a column is generated as, near borders columns are randomly
+    /// picked by their weights so neighbouring biomes blend into each other
+    pub fn biome_at(&self, x: i32, y: i32) -> Biome {
+        let w = self.weights(x, y);
+        let mut r = rand3(self.seed, x, y, 0);
+        for (b, w) in Biome::ALL.iter().zip(w) {
+            if r < w {
+                return *b;
+            }
+            r -= w;
+        }
+        self.dominant(x, y)
+    }
+}
+#[test]
+fn biome_source_test() {
+    let source = BiomeSource::new(99);
+    let mut seen = [false; Biome::ALL.len()];
+    for i in 0..4000 {
+        let (x, y) = ((i % 64) * 23, (i / 64) * 23);
+        let w = source.weights(x, y);
+        assert!((w.iter().sum::<f32>() - 1.).abs() < 1e-4);
+        assert_eq!(source.biome_at(x, y), BiomeSource::new(99).biome_at(x, y));
+        seen[source.dominant(x, y) as usize] = true;
+    }
+    assert!(
+        seen.iter().all(|s| *s),
+        "some biomes never show up: {seen:?}"
+    );
+}
@@ -0,0 +1,192 @@
+/****************************************
+   Block Entities
+   data owned by a single block, like the
+   progress and settings of a machine, they
+   come and go with their block, get ticked
+   with the world and are saved with it
+****************************************/
+use std::collections::HashMap;
+
+use macroquad::math::{IVec2, IVec3};
+
+use crate::{chunk::chunk_of, power::Power, state::BlockState, world::World};
+
+/// ticks a machine works on one job
+pub const MACHINE_JOB_TICKS: u32 = 100;
+
+/// a machine works away at jobs while it's enabled and powered, keeping what it made
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Machine {
+    /// whether the machine is switched on
+    pub enabled: bool,
+    /// whether power reached the machine on it's last tick, not saved as it's
+    /// worked out from the blocks around it again
+    pub powered: bool,
+    /// ticks spent on the current job
+    pub progress: u32,
+    /// jobs finished and waiting to be taken out
+    pub output: u32,
+}
+impl Machine {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            powered: false,
+            progress: 0,
+            output: 0,
+        }
+    }
+    fn tick(&mut self, power: u8) {
+        self.powered = power > 0;
+        if !self.enabled || !self.powered {
+            return;
+        }
+        self.progress += 1;
+        if self.progress >= MACHINE_JOB_TICKS {
+            self.progress = 0;
+            self.output += 1;
+        }
+    }
+}
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// the data a block entity holds, which one a block gets is set in it's registry entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockEntity {
+    Machine(Machine),
+}
+impl BlockEntity {
+    pub fn machine() -> Self {
+        BlockEntity::Machine(Machine::new())
+    }
+    /// `power` is the signal reaching the entity's block, see [`crate::power`]
+    pub fn tick(&mut self, power: u8) {
+        match self {
+            BlockEntity::Machine(m) => m.tick(power),
+        }
+    }
+    /// a line about the entity for the player to read
+    pub fn describe(&self) -> String {
+        match self {
+            BlockEntity::Machine(m) => format!(
+                "Machine {}, {}, job {}%, {} made",
+                if m.enabled { "on" } else { "off" },
+                if m.powered { "powered" } else { "no power" },
+                m.progress * 100 / MACHINE_JOB_TICKS,
+                m.output
+            ),
+        }
+    }
+    /// the entity's data as bytes for saves
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            BlockEntity::Machine(m) => {
+                let mut data = vec![m.enabled as u8];
+                data.extend(m.progress.to_le_bytes());
+                data.extend(m.output.to_le_bytes());
+                data
+            }
+        }
+    }
+    /// reads back what [`BlockEntity::encode`] wrote into an entity of the same kind,
+    /// `None` if the data doesn't fit
+    pub fn decode(&mut self, data: &[u8]) -> Option<()> {
+        match self {
+            BlockEntity::Machine(m) => {
+                let (&enabled, rest) = data.split_first()?;
+                let (progress, output) = (rest.get(0..4)?, rest.get(4..8)?);
+                m.enabled = enabled != 0;
+                m.progress = u32::from_le_bytes(progress.try_into().ok()?);
+                m.output = u32::from_le_bytes(output.try_into().ok()?);
+                Some(())
+            }
+        }
+    }
+}
+
+impl World {
+    pub fn block_entity(&self, pos: IVec3) -> Option<&BlockEntity> {
+        self.block_entities.get(&pos)
+    }
+    pub fn block_entity_mut(&mut self, pos: IVec3) -> Option<&mut BlockEntity> {
+        self.block_entities.get_mut(&pos)
+    }
+    /// every block entity with the position of it's block
+    pub fn block_entities(&self) -> impl Iterator<Item = (IVec3, &BlockEntity)> {
+        self.block_entities.iter().map(|(p, e)| (*p, e))
+    }
+    /// swaps the entity of a block that changed from `old` to `new`,
+    /// blocks that only changed their state keep theirs
+    pub(crate) fn replace_block_entity(&mut self, pos: IVec3, old: BlockState, new: BlockState) {
+        if old.id == new.id {
+            return;
+        }
+        self.block_entities.remove(&pos);
+        if let Some(create) = self.registry().get(new.id).block_entity {
+            self.block_entities.insert(pos, create());
+        }
+    }
+    /// gives the blocks of a chunk that was just put into the world fresh entities
+    pub(crate) fn create_chunk_block_entities(&mut self, chunk: IVec2) {
+        self.block_entities.retain(|p, _| chunk_of(*p) != chunk);
+        let created: HashMap<IVec3, BlockEntity> = self
+            .chunk_blocks(chunk)
+            .filter_map(|(p, b)| Some((p, self.registry().get(b.id).block_entity?())))
+            .collect();
+        self.block_entities.extend(created);
+    }
+    /// ticks every entity with the power reaching it, blocks that aren't consumers get none
+    pub(crate) fn tick_block_entities(&mut self) {
+        let powered: Vec<(IVec3, u8)> = self
+            .block_entities
+            .keys()
+            .map(|p| {
+                let consumer =
+                    self.registry().get(self.get_block(*p)).power == Some(Power::Consumer);
+                (*p, if consumer { self.power_at(*p) } else { 0 })
+            })
+            .collect();
+        for (pos, power) in powered {
+            if let Some(e) = self.block_entities.get_mut(&pos) {
+                e.tick(power);
+            }
+        }
+    }
+}
+#[test]
+fn block_entity_test() {
+    use crate::registry::{AIR, GENERATOR, MACHINE, STONE};
+    use macroquad::math::ivec3;
+    let mut world = World::new();
+    let pos = ivec3(2, 3, 4);
+    world.set_block(pos, STONE).unwrap();
+    assert!(world.block_entity(pos).is_none());
+    // placing a machine gives it an entity, it waits for power
+    world.set_block(pos, MACHINE).unwrap();
+    world.tick();
+    let BlockEntity::Machine(m) = world.block_entity(pos).unwrap();
+    assert_eq!((m.powered, m.progress), (false, 0));
+    // and works with the ticks once it's got some
+    world.set_block(pos + IVec3::Z, GENERATOR).unwrap();
+    for _ in 0..MACHINE_JOB_TICKS * 2 + 10 {
+        world.tick();
+    }
+    let BlockEntity::Machine(m) = world.block_entity(pos).unwrap();
+    assert_eq!((m.output, m.progress), (2, 10));
+    // turning it keeps the entity
+    let turned = world.get_state(pos).with_facing(crate::state::Facing::West);
+    world.set_state(pos, turned).unwrap();
+    assert!(world.block_entity(pos).is_some());
+    // and the data survives being written out, power is worked out again on the next tick
+    let data = world.block_entity(pos).unwrap().encode();
+    let mut copy = BlockEntity::machine();
+    copy.decode(&data).unwrap();
+    assert_eq!(copy.encode(), data);
+    // breaking the block takes the entity with it
+    world.set_block(pos, AIR).unwrap();
+    assert!(world.block_entity(pos).is_none());
+}
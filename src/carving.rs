@@ -0,0 +1,194 @@
+/****************************************
+   Caves and Ores
+   passes run over freshly generated terrain:
+   caves hollow out tunnels and caverns with
+   3d noise, ores swap stone for other blocks
+   in small veins depending on depth
+****************************************/
+use macroquad::math::{ivec3, IVec2, IVec3};
+
+use crate::{
+    chunk::{CHUNK_SIZE, HEIGHT},
+    noise::{hash3, rand3, sub_seed, Noise},
+    registry::{BlockId, DIRT, GRAVEL, SMOOTH_STONE, STONE},
+    world::World,
+};
+
+/// decides which blocks below the surface are hollowed out
+#[derive(Debug, Clone)]
+pub struct CaveCarver {
+    tunnel_a: Noise,
+    tunnel_b: Noise,
+    cavern: Noise,
+    /// size of tunnel bends in blocks
+    pub scale: f32,
+    /// how wide tunnels are, tunnels are where two noise fields are both close to 0
+    pub tunnel_width: f32,
+    /// noise value above which big caverns open up, above 1 turns caverns off
+    pub cavern_threshold: f32,
+    /// caverns only open up below this height
+    pub cavern_max_z: i32,
+    /// nothing below this height is carved so the world keeps a floor
+    pub min_z: i32,
+    /// how many blocks under the surface stay solid, 0 lets tunnels break through
+    pub surface_margin: i32,
+}
+impl CaveCarver {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            tunnel_a: Noise::new(sub_seed(seed, 20)),
+            tunnel_b: Noise::new(sub_seed(seed, 21)),
+            cavern: Noise::new(sub_seed(seed, 22)),
+            scale: 24.,
+            tunnel_width: 0.09,
+            cavern_threshold: 0.55,
+            cavern_max_z: HEIGHT / 3,
+            min_z: 1,
+            surface_margin: 0,
+        }
+    }
+    /// whether the block at a position is part of a cave, `height` is the top of it's column
+    pub fn is_cave(&self, pos: IVec3, height: i32) -> bool {
+        if pos.z < self.min_z || pos.z > height - self.surface_margin || pos.z >= HEIGHT {
+            return false;
+        }
+        let (x, y, z) = (
+            pos.x as f32 / self.scale,
+            pos.y as f32 / self.scale,
+            // squash vertically so tunnels run more sideways than up and down
+            pos.z as f32 / self.scale * 2.,
+        );
+        let tunnel = self.tunnel_a.get3(x, y, z).abs() < self.tunnel_width
+            && self.tunnel_b.get3(x, y, z).abs() < self.tunnel_width;
+        let cavern = pos.z <= self.cavern_max_z
+            && self.cavern.get3(x * 0.5, y * 0.5, z) > self.cavern_threshold;
+        tunnel || cavern
+    }
+}
+
+/// a kind of block scattered in small veins through stone
+#[derive(Debug, Clone)]
+pub struct OreVein {
+    pub block: BlockId,
+    /// the only block veins are allowed to replace
+    pub replaces: BlockId,
+    /// veins are only placed between these heights (inclusive)
+    pub min_z: i32,
+    pub max_z: i32,
+    /// average count of veins in a chunk
+    pub per_chunk: f32,
+    /// how many blocks a vein tries to grow to
+    pub size: u32,
+}
+impl OreVein {
+    /// the veins generated by default, from deepest to shallowest
+    pub fn defaults() -> Vec<OreVein> {
+        vec![
+            OreVein {
+                block: SMOOTH_STONE,
+                replaces: STONE,
+                min_z: 1,
+                max_z: HEIGHT / 4,
+                per_chunk: 6.,
+                size: 6,
+            },
+            OreVein {
+                block: GRAVEL,
+                replaces: STONE,
+                min_z: 4,
+                max_z: HEIGHT / 2,
+                per_chunk: 4.,
+                size: 12,
+            },
+            OreVein {
+                block: DIRT,
+                replaces: STONE,
+                min_z: HEIGHT / 4,
+                max_z: HEIGHT - 1,
+                per_chunk: 3.,
+                size: 16,
+            },
+        ]
+    }
+    /// grows this chunk's veins, veins never leave the chunk so chunks can be generated in any order
+    pub fn scatter(&self, seed: u64, salt: u64, world: &mut World, chunk: IVec2) {
+        let seed = sub_seed(seed, 30 + salt);
+        let whole = self.per_chunk.floor() as i32;
+        let count = whole + (rand3(seed, chunk.x, chunk.y, -1) < self.per_chunk.fract()) as i32;
+        let origin = ivec3(chunk.x * CHUNK_SIZE, chunk.y * CHUNK_SIZE, 0);
+        for v in 0..count {
+            let h = hash3(seed, chunk.x, chunk.y, v);
+            let mut pos = origin
+                + ivec3(
+                    (h % CHUNK_SIZE as u64) as i32,
+                    ((h >> 8) % CHUNK_SIZE as u64) as i32,
+                    self.min_z + ((h >> 16) % (self.max_z - self.min_z + 1).max(1) as u64) as i32,
+                );
+            // random walk from the vein's origin
+            for step in 0..self.size {
+                if world.get_block(pos) == self.replaces {
+                    world.set_block(pos, self.block).unwrap();
+                }
+                let d = match hash3(h, step as i32, 0, 0) % 6 {
+                    0 => IVec3::X,
+                    1 => IVec3::NEG_X,
+                    2 => IVec3::Y,
+                    3 => IVec3::NEG_Y,
+                    4 => IVec3::Z,
+                    _ => IVec3::NEG_Z,
+                };
+                let next = pos + d;
+                let local = next - origin;
+                if (0..CHUNK_SIZE).contains(&local.x)
+                    && (0..CHUNK_SIZE).contains(&local.y)
+                    && (self.min_z..=self.max_z).contains(&next.z)
+                {
+                    pos = next;
+                }
+            }
+        }
+    }
+}
+#[test]
+fn caves_keep_the_floor_test() {
+    let carver = CaveCarver::new(5);
+    let mut carved = 0;
+    for x in 0..48 {
+        for y in 0..48 {
+            assert!(!carver.is_cave(ivec3(x, y, 0), 40));
+            assert!(!carver.is_cave(ivec3(x, y, 41), 40));
+            carved += (1..40)
+                .filter(|z| carver.is_cave(ivec3(x, y, *z), 40))
+                .count();
+        }
+    }
+    let total = 48 * 48 * 39;
+    assert!(
+        carved > 0 && carved < total / 4,
+        "{carved} of {total} carved"
+    );
+}
+#[test]
+fn ores_stay_in_range_test() {
+    use macroquad::math::ivec2;
+    let mut world = World::new();
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for z in 0..HEIGHT {
+                world.set_block(ivec3(x, y, z), STONE).unwrap();
+            }
+        }
+    }
+    let vein = OreVein {
+        block: GRAVEL,
+        replaces: STONE,
+        min_z: 10,
+        max_z: 20,
+        per_chunk: 8.,
+        size: 10,
+    };
+    vein.scatter(3, 0, &mut world, ivec2(0, 0));
+    let ores: Vec<_> = world.blocks().filter(|(_, s)| s.id == GRAVEL).collect();
+    assert!(!ores.is_empty());
+    assert!(ores.iter().all(|(p, _)| (10..=20).contains(&p.z)));
+}
@@ -0,0 +1,206 @@
+use macroquad::math::{ivec2, ivec3, IVec2, IVec3};
+
+use crate::{
+    biome::Biome,
+    section::{LightSection, Section, SECTION_HEIGHT, SECTION_VOLUME},
+    state::BlockState,
+};
+
+/// width and depth of a chunk in blocks (chunks always span the whole world height)
+pub const CHUNK_SIZE: i32 = 16;
+/// height of the world in blocks, every chunk is this tall
+pub const HEIGHT: i32 = 64;
+const CHUNK_AREA: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+const CHUNK_VOL: usize = CHUNK_AREA * HEIGHT as usize;
+const SECTIONS: usize = (HEIGHT / SECTION_HEIGHT) as usize;
+
+/// the highest blocks of a column, kept up to date by the world
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ColumnTop {
+    /// z of the highest block entities collide with
+    pub solid: Option<i32>,
+    /// z of the highest block that can't be seen through
+    pub opaque: Option<i32>,
+}
+
+/// a `CHUNK_SIZE` x `CHUNK_SIZE` column of blocks spanning the entire height of the world
+///
+/// Note: positions used by a chunk are local to it, use [`chunk_of`] and [`local_of`]
+/// to go from a world position to a chunk and back
+pub struct Chunk {
+    /// the blocks, a section every `SECTION_HEIGHT` blocks from the bottom up
+    sections: [Section; SECTIONS],
+    /// biome of every column in x, then y order
+    biomes: [Biome; CHUNK_AREA],
+    /// heightmap of every column in x, then y order
+    tops: [ColumnTop; CHUNK_AREA],
+    /// sky light in the high and block light in the low 4 bits of every block,
+    /// sectioned like the blocks
+    light: [LightSection; SECTIONS],
+    /// count of non-air blocks so empty chunks can be skipped or dropped
+    filled: usize,
+}
+impl Chunk {
+    /// number of blocks in a chunk
+    pub const VOLUME: usize = CHUNK_VOL;
+    pub fn new() -> Self {
+        Self {
+            sections: Default::default(),
+            biomes: [Biome::default(); CHUNK_AREA],
+            tops: [ColumnTop::default(); CHUNK_AREA],
+            light: Default::default(),
+            filled: 0,
+        }
+    }
+    #[inline]
+    fn index(local: IVec3) -> usize {
+        debug_assert!(Self::contains(local), "{local} is not inside a chunk");
+        (local.z as usize * CHUNK_AREA) + (local.y * CHUNK_SIZE + local.x) as usize
+    }
+    /// whether a local position falls inside the bounds of a chunk
+    #[inline]
+    pub fn contains(local: IVec3) -> bool {
+        (0..CHUNK_SIZE).contains(&local.x)
+            && (0..CHUNK_SIZE).contains(&local.y)
+            && (0..HEIGHT).contains(&local.z)
+    }
+    pub fn get(&self, local: IVec3) -> BlockState {
+        let i = Self::index(local);
+        self.sections[i / SECTION_VOLUME].get(i % SECTION_VOLUME)
+    }
+    pub fn set(&mut self, local: IVec3, b: BlockState) {
+        let i = Self::index(local);
+        let section = &mut self.sections[i / SECTION_VOLUME];
+        match (section.get(i % SECTION_VOLUME).id, b.id) {
+            (0, 0) => {}
+            (0, _) => self.filled += 1,
+            (_, 0) => self.filled -= 1,
+            _ => {}
+        }
+        section.set(i % SECTION_VOLUME, b);
+    }
+    /// packed sky and block light of a block, see [`crate::light`]
+    pub fn light(&self, local: IVec3) -> u8 {
+        let i = Self::index(local);
+        self.light[i / SECTION_VOLUME].get(i % SECTION_VOLUME)
+    }
+    pub fn set_light(&mut self, local: IVec3, light: u8) {
+        let i = Self::index(local);
+        self.light[i / SECTION_VOLUME].set(i % SECTION_VOLUME, light);
+    }
+    /// makes every block of the chunk dark
+    pub fn clear_light(&mut self) {
+        self.light = Default::default();
+    }
+    /// builds a chunk out of `Chunk::VOLUME` states laid out in x, then y, then z order
+    pub fn from_states(states: Vec<BlockState>) -> Self {
+        assert_eq!(states.len(), CHUNK_VOL);
+        let mut sections = states.chunks(SECTION_VOLUME).map(Section::from_states);
+        Self {
+            filled: states.iter().filter(|b| b.id != 0).count(),
+            sections: std::array::from_fn(|_| sections.next().unwrap()),
+            biomes: [Biome::default(); CHUNK_AREA],
+            tops: [ColumnTop::default(); CHUNK_AREA],
+            light: Default::default(),
+        }
+    }
+    /// every state of the chunk, air included, in x, then y, then z order
+    pub fn states(&self) -> impl Iterator<Item = BlockState> + '_ {
+        self.sections.iter().flat_map(|s| s.states())
+    }
+    /// drops states no block uses anymore from the palettes of the sections
+    /// and lets sections lit the same everywhere keep a single level
+    pub fn compact(&mut self) {
+        for section in &mut self.sections {
+            section.compact();
+        }
+        for light in &mut self.light {
+            light.compact();
+        }
+    }
+    /// bytes the blocks and the light of the chunk take up on the heap, see [`crate::section`]
+    #[cfg(test)]
+    pub fn heap_size(&self) -> usize {
+        self.sections.iter().map(Section::heap_size).sum::<usize>()
+            + self
+                .light
+                .iter()
+                .map(LightSection::heap_size)
+                .sum::<usize>()
+    }
+    /// biome of a column, `x` and `y` are local to the chunk
+    pub fn biome(&self, x: i32, y: i32) -> Biome {
+        self.biomes[(y * CHUNK_SIZE + x) as usize]
+    }
+    pub fn set_biome(&mut self, x: i32, y: i32, biome: Biome) {
+        self.biomes[(y * CHUNK_SIZE + x) as usize] = biome;
+    }
+    /// biome of every column in x, then y order
+    pub fn biomes(&self) -> &[Biome] {
+        &self.biomes
+    }
+    /// highest blocks of a column, `x` and `y` are local to the chunk
+    pub fn top(&self, x: i32, y: i32) -> ColumnTop {
+        self.tops[(y * CHUNK_SIZE + x) as usize]
+    }
+    pub fn set_top(&mut self, x: i32, y: i32, top: ColumnTop) {
+        self.tops[(y * CHUNK_SIZE + x) as usize] = top;
+    }
+    pub fn is_empty(&self) -> bool {
+        self.filled == 0
+    }
+    /// all non-air blocks of this chunk with their local positions
+    pub fn blocks(&self) -> impl Iterator<Item = (IVec3, BlockState)> + '_ {
+        self.sections
+            .iter()
+            .enumerate()
+            // sections of nothing but air don't need looking at
+            .filter(|(_, s)| s.uniform() != Some(BlockState::AIR))
+            .flat_map(|(n, s)| {
+                s.states()
+                    .enumerate()
+                    .map(move |(i, b)| (n * SECTION_VOLUME + i, b))
+            })
+            .filter(|(_, b)| b.id != 0)
+            .map(|(i, b)| {
+                let z = (i / CHUNK_AREA) as i32;
+                let i = (i % CHUNK_AREA) as i32;
+                (ivec3(i % CHUNK_SIZE, i / CHUNK_SIZE, z), b)
+            })
+    }
+}
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+/// chunk coordinates of the chunk holding a world position
+#[inline]
+pub fn chunk_of(pos: IVec3) -> IVec2 {
+    ivec2(pos.x.div_euclid(CHUNK_SIZE), pos.y.div_euclid(CHUNK_SIZE))
+}
+/// position of a world position inside of it's chunk
+#[inline]
+pub fn local_of(pos: IVec3) -> IVec3 {
+    ivec3(
+        pos.x.rem_euclid(CHUNK_SIZE),
+        pos.y.rem_euclid(CHUNK_SIZE),
+        pos.z,
+    )
+}
+/// world position of a local position inside a chunk
+#[inline]
+pub fn world_of(chunk: IVec2, local: IVec3) -> IVec3 {
+    ivec3(
+        chunk.x * CHUNK_SIZE + local.x,
+        chunk.y * CHUNK_SIZE + local.y,
+        local.z,
+    )
+}
+#[test]
+fn chunk_coords_test() {
+    let p = ivec3(-1, 17, 3);
+    assert_eq!(chunk_of(p), ivec2(-1, 1));
+    assert_eq!(local_of(p), ivec3(15, 1, 3));
+    assert_eq!(world_of(chunk_of(p), local_of(p)), p);
+}
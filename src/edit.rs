@@ -0,0 +1,269 @@
+/****************************************
+   Bulk Editing
+   operations changing many blocks at once
+   (boxes, spheres, lines, copy and paste...),
+   everything is clipped to the world's height
+   and returns how many blocks were changed,
+   an edit touching a chunk that isn't loaded
+   fails before changing anything
+****************************************/
+use macroquad::math::{ivec2, ivec3, IVec3};
+
+use crate::{
+    chunk::{chunk_of, HEIGHT},
+    registry::{BlockId, AIR},
+    state::BlockState,
+    structure::Structure,
+    world::{World, WorldError},
+};
+
+/// every position in the box between two corners (inclusive) that's inside the world
+fn cells(from: IVec3, to: IVec3) -> impl Iterator<Item = IVec3> {
+    let (min, max) = (from.min(to), from.max(to));
+    let (z0, z1) = (min.z.max(0), max.z.min(HEIGHT - 1));
+    (z0..=z1).flat_map(move |z| {
+        (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| ivec3(x, y, z)))
+    })
+}
+
+impl World {
+    /// fails if any chunk the box between two corners touches isn't loaded
+    fn check_loaded(&self, from: IVec3, to: IVec3) -> Result<(), WorldError> {
+        let (min, max) = (chunk_of(from.min(to)), chunk_of(from.max(to)));
+        for cy in min.y..=max.y {
+            for cx in min.x..=max.x {
+                if !self.is_loaded(ivec2(cx, cy)) {
+                    return Err(WorldError::NotLoaded(ivec2(cx, cy)));
+                }
+            }
+        }
+        Ok(())
+    }
+    /// sets the blocks that are inside the world and different once every chunk
+    /// of the box they're in is known to be loaded, returns how many changed
+    fn edit_cells(
+        &mut self,
+        from: IVec3,
+        to: IVec3,
+        cells: impl IntoIterator<Item = (IVec3, BlockState)>,
+    ) -> Result<usize, WorldError> {
+        self.check_loaded(from, to)?;
+        let mut changed = 0;
+        for (pos, state) in cells {
+            if World::in_bounds(pos) && self.get_state(pos) != state {
+                self.set_state(pos, state)?;
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+    /// sets every block of a box
+    pub fn fill(
+        &mut self,
+        from: IVec3,
+        to: IVec3,
+        state: impl Into<BlockState>,
+    ) -> Result<usize, WorldError> {
+        let state = state.into();
+        self.edit_cells(from, to, cells(from, to).map(|p| (p, state)))
+    }
+    /// sets the walls, floor and ceiling of a box and clears the inside
+    pub fn hollow_box(
+        &mut self,
+        from: IVec3,
+        to: IVec3,
+        state: impl Into<BlockState>,
+    ) -> Result<usize, WorldError> {
+        let (state, min, max) = (state.into(), from.min(to), from.max(to));
+        let walls = cells(min, max).map(|p| {
+            let wall = p.cmpeq(min).any() || p.cmpeq(max).any();
+            (p, if wall { state } else { BlockState::AIR })
+        });
+        self.edit_cells(min, max, walls)
+    }
+    /// swaps every block of one kind in a box for another
+    pub fn replace(
+        &mut self,
+        from: IVec3,
+        to: IVec3,
+        old: BlockId,
+        new: impl Into<BlockState>,
+    ) -> Result<usize, WorldError> {
+        let new = new.into();
+        // air isn't kept anywhere, every other block can be looked up by chunk
+        let cells: Vec<IVec3> = if old == AIR {
+            cells(from, to)
+                .filter(|p| self.get_block(*p) == AIR)
+                .collect()
+        } else {
+            self.blocks_in(from, to)
+                .filter(|(_, b)| b.id == old)
+                .map(|(p, _)| p)
+                .collect()
+        };
+        self.edit_cells(from, to, cells.into_iter().map(|p| (p, new)))
+    }
+    /// a ball of blocks, a hollow one is only a shell one block thick
+    pub fn sphere(
+        &mut self,
+        center: IVec3,
+        radius: f32,
+        state: impl Into<BlockState>,
+        hollow: bool,
+    ) -> Result<usize, WorldError> {
+        let state = state.into();
+        let r = radius.ceil() as i32;
+        // half a block of slack so small spheres aren't just a cross
+        let outer = (radius + 0.5).powi(2);
+        let inner = (radius - 0.5).powi(2);
+        let ball = cells(center - r, center + r).filter(|p| {
+            let d = (*p - center).as_vec3().length_squared();
+            d <= outer && !(hollow && d < inner)
+        });
+        self.edit_cells(center - r, center + r, ball.map(|p| (p, state)))
+    }
+    /// an upright cylinder standing on `base`, a negative height grows downwards
+    pub fn cylinder(
+        &mut self,
+        base: IVec3,
+        radius: f32,
+        height: i32,
+        state: impl Into<BlockState>,
+    ) -> Result<usize, WorldError> {
+        let state = state.into();
+        let r = radius.ceil() as i32;
+        let top = base.z + height - height.signum();
+        let outer = (radius + 0.5).powi(2);
+        let (from, to) = (base - ivec3(r, r, 0), ivec3(base.x + r, base.y + r, top));
+        let disks = cells(from, to).filter(|p| {
+            let d = (*p - base).truncate().as_vec2().length_squared();
+            d <= outer
+        });
+        self.edit_cells(from, to, disks.map(|p| (p, state)))
+    }
+    /// a line of blocks between two points (inclusive) without gaps along it's longest axis
+    pub fn line(
+        &mut self,
+        from: IVec3,
+        to: IVec3,
+        state: impl Into<BlockState>,
+    ) -> Result<usize, WorldError> {
+        let state = state.into();
+        let d = to - from;
+        let steps = d.abs().max_element();
+        let line = (0..=steps).map(|i| {
+            let t = if steps == 0 {
+                0.
+            } else {
+                i as f32 / steps as f32
+            };
+            (from + (d.as_vec3() * t).round().as_ivec3(), state)
+        });
+        self.edit_cells(from, to, line)
+    }
+    /// every block of a box (air included) as a template, `anchor` is the
+    /// world position that lines up with where it gets pasted
+    pub fn copy(&self, from: IVec3, to: IVec3, anchor: IVec3) -> Result<Structure, WorldError> {
+        // chunks that aren't loaded would read as air
+        self.check_loaded(from, to)?;
+        let min = from.min(to);
+        let blocks = cells(from, to)
+            .map(|p| (p - min, self.get_state(p)))
+            .collect();
+        Ok(Structure::from_blocks("clipboard", anchor - min, blocks))
+    }
+    /// copies a box and clears it
+    pub fn cut(&mut self, from: IVec3, to: IVec3, anchor: IVec3) -> Result<Structure, WorldError> {
+        let copy = self.copy(from, to, anchor)?;
+        self.fill(from, to, BlockState::AIR)?;
+        Ok(copy)
+    }
+}
+#[test]
+fn shapes_test() {
+    use crate::registry::{DIRT, STONE};
+    let mut world = World::new();
+    assert_eq!(world.fill(ivec3(0, 0, 0), ivec3(2, 2, 2), STONE), Ok(27));
+    // nothing changes the second time
+    assert_eq!(world.fill(ivec3(2, 2, 2), ivec3(0, 0, 0), STONE), Ok(0));
+    assert_eq!(
+        world.hollow_box(ivec3(0, 0, 0), ivec3(2, 2, 2), DIRT),
+        Ok(27)
+    );
+    assert_eq!(world.get_block(ivec3(1, 1, 1)), 0);
+    assert_eq!(
+        world.replace(ivec3(0, 0, 0), ivec3(2, 2, 0), DIRT, STONE),
+        Ok(9)
+    );
+    assert_eq!(world.get_block(ivec3(1, 1, 0)), STONE);
+    assert_eq!(world.get_block(ivec3(1, 1, 2)), DIRT);
+    // clipped to the bottom of the world
+    assert_eq!(world.fill(ivec3(10, 0, -5), ivec3(10, 0, 0), STONE), Ok(1));
+
+    let mut world = World::new();
+    let c = ivec3(0, 0, 20);
+    let solid = world.sphere(c, 3., STONE, false).unwrap();
+    let mut shell = World::new();
+    let hollow = shell.sphere(c, 3., STONE, true).unwrap();
+    assert!(hollow < solid && world.get_block(c) == STONE && shell.get_block(c) == 0);
+    assert_eq!(world.get_block(c + ivec3(3, 0, 0)), STONE);
+    assert_eq!(world.get_block(c + ivec3(3, 3, 0)), 0);
+
+    let mut world = World::new();
+    assert_eq!(world.cylinder(ivec3(0, 0, 5), 0., 3, STONE), Ok(3));
+    assert_eq!(world.cylinder(ivec3(0, 0, 5), 0., -3, DIRT), Ok(3));
+    assert_eq!(world.get_block(ivec3(0, 0, 3)), DIRT);
+    assert_eq!(world.get_block(ivec3(0, 0, 7)), STONE);
+    assert_eq!(world.line(ivec3(0, 0, 10), ivec3(6, 3, 10), STONE), Ok(7));
+    assert_eq!(world.get_block(ivec3(6, 3, 10)), STONE);
+}
+#[test]
+fn copy_paste_test() {
+    use crate::{
+        registry::{MACHINE, STONE},
+        state::Facing,
+        structure::{Mirror, Rotation},
+    };
+    let mut world = World::new();
+    let machine = BlockState::new(MACHINE).with_facing(Facing::East);
+    world.set_block(ivec3(0, 0, 0), STONE).unwrap();
+    world.set_state(ivec3(1, 0, 0), machine).unwrap();
+    let copy = world
+        .cut(ivec3(0, 0, 0), ivec3(1, 0, 0), ivec3(0, 0, 0))
+        .unwrap();
+    assert_eq!(world.blocks().count(), 0);
+    copy.place(&mut world, ivec3(10, 10, 0), Rotation::Clockwise)
+        .unwrap();
+    assert_eq!(world.get_block(ivec3(10, 10, 0)), STONE);
+    assert_eq!(world.get_state(ivec3(10, 11, 0)).facing(), Facing::South);
+    copy.mirrored(Mirror::X, world.registry())
+        .place(&mut world, ivec3(20, 0, 0), Rotation::None)
+        .unwrap();
+    assert_eq!(world.get_state(ivec3(19, 0, 0)).facing(), Facing::West);
+}
+#[test]
+fn unloaded_edit_test() {
+    use crate::{chunk::CHUNK_SIZE, registry::STONE, structure::Rotation};
+    use macroquad::math::ivec2;
+    let mut world = World::new();
+    world.set_block(ivec3(0, 0, 0), STONE).unwrap();
+    world.set_streamed(true);
+    // the box reaches into the chunk east of the only loaded one
+    let (a, b) = (ivec3(0, 0, 0), ivec3(CHUNK_SIZE, 1, 1));
+    let not_loaded = Err(WorldError::NotLoaded(ivec2(1, 0)));
+    assert_eq!(world.fill(a, b, STONE), not_loaded);
+    assert_eq!(world.line(b, a, STONE), not_loaded);
+    assert_eq!(
+        world.cut(a, b, a).err(),
+        Some(WorldError::NotLoaded(ivec2(1, 0)))
+    );
+    // nothing was changed in the loaded chunk either
+    assert_eq!(world.blocks().count(), 1);
+    let copy = world.copy(a, a + 1, a).unwrap();
+    assert_eq!(
+        copy.place(&mut world, ivec3(CHUNK_SIZE - 1, 0, 5), Rotation::None),
+        Err(WorldError::NotLoaded(ivec2(1, 0)))
+    );
+    assert_eq!(world.blocks().count(), 1);
+    assert_eq!(world.fill(a, a + 1, STONE), Ok(7));
+}
@@ -0,0 +1,133 @@
+/****************************************
+   Entities
+   things in the world that aren't stuck to
+   the block grid: blocks falling down and the
+   items they turn into when they can't land
+****************************************/
+use macroquad::math::{IVec3, Vec3};
+
+use crate::{chunk::chunk_of, registry::AIR, state::BlockState, world::World};
+
+/// how fast entities speed up while falling, in blocks per second squared
+const GRAVITY: f32 = 25.;
+/// the fastest entities can fall, in blocks per second
+const MAX_FALL_SPEED: f32 = 40.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntityKind {
+    /// a block that lost it's support, it turns back into a block where it lands
+    FallingBlock(BlockState),
+    /// a block that had nowhere to land, waits on the ground for the player to pick it up
+    Item(BlockState),
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entity {
+    /// lowest corner of the entity, like blocks it takes up the cell from there
+    pub pos: Vec3,
+    /// how fast it's falling in blocks per second
+    pub fall_speed: f32,
+    pub kind: EntityKind,
+}
+impl Entity {
+    pub fn new(pos: Vec3, kind: EntityKind) -> Self {
+        Self {
+            pos,
+            fall_speed: 0.,
+            kind,
+        }
+    }
+}
+
+impl World {
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+    pub fn spawn(&mut self, entity: Entity) {
+        self.entities.push(entity);
+    }
+    /// takes every entity matching `f` out of the world
+    pub fn remove_entities(&mut self, mut f: impl FnMut(&Entity) -> bool) -> Vec<Entity> {
+        let (removed, kept) = std::mem::take(&mut self.entities)
+            .into_iter()
+            .partition(|e| f(e));
+        self.entities = kept;
+        removed
+    }
+    /// whether something falling stops on top of a block, the bottom of the world stops everything
+    fn stops_fall(&self, pos: IVec3) -> bool {
+        pos.z < 0 || self.registry().is_solid(self.get_block(pos))
+    }
+    /// moves every entity `dt` seconds forward, entities in chunks that aren't loaded wait
+    pub fn step_entities(&mut self, dt: f32) {
+        let mut entities = std::mem::take(&mut self.entities);
+        entities.retain_mut(|e| {
+            if self.chunk(chunk_of(e.pos.floor().as_ivec3())).is_none() {
+                return true;
+            }
+            e.fall_speed = (e.fall_speed + GRAVITY * dt).min(MAX_FALL_SPEED);
+            let z = e.pos.z - e.fall_speed * dt;
+            let cell = e.pos.floor().as_ivec3();
+            // every cell passed this step, fast entities can pass more than one
+            let floor = (z.floor() as i32..cell.z)
+                .rev()
+                .find(|cz| self.stops_fall(cell.with_z(*cz)));
+            match floor {
+                Some(cz) => self.land(e, cell.with_z(cz + 1)),
+                None => {
+                    e.pos.z = z;
+                    true
+                }
+            }
+        });
+        // landing can spawn entities too
+        entities.append(&mut self.entities);
+        self.entities = entities;
+    }
+    /// puts a falling entity down in a cell, returns whether it's still an entity after
+    fn land(&mut self, e: &mut Entity, cell: IVec3) -> bool {
+        e.pos.z = cell.z as f32;
+        e.fall_speed = 0.;
+        let EntityKind::FallingBlock(state) = e.kind else {
+            return true;
+        };
+        // fluids get pushed out of the way but anything else is in the way
+        let here = self.get_block(cell);
+        if (here == AIR || self.registry().is_fluid(here)) && self.set_state(cell, state).is_ok() {
+            return false;
+        }
+        e.kind = EntityKind::Item(state);
+        true
+    }
+}
+#[test]
+fn falling_entity_test() {
+    use crate::registry::{SAND, STONE, WHEAT};
+    use macroquad::math::{ivec3, vec3};
+    let mut world = World::new();
+    world.set_block(ivec3(0, 0, 0), STONE).unwrap();
+    world.spawn(Entity::new(
+        vec3(0., 0., 10.),
+        EntityKind::FallingBlock(SAND.into()),
+    ));
+    // big steps can't fall trough the floor
+    for _ in 0..10 {
+        world.step_entities(0.2);
+    }
+    assert!(world.entities().is_empty());
+    assert_eq!(world.get_block(ivec3(0, 0, 1)), SAND);
+    // something in the way of the landing turns it into an item
+    world.set_block(ivec3(0, 0, 2), WHEAT).unwrap();
+    world.spawn(Entity::new(
+        vec3(0., 0., 5.),
+        EntityKind::FallingBlock(SAND.into()),
+    ));
+    for _ in 0..10 {
+        world.step_entities(0.1);
+    }
+    assert_eq!(world.get_block(ivec3(0, 0, 2)), WHEAT);
+    assert_eq!(world.entities()[0].kind, EntityKind::Item(SAND.into()));
+    assert_eq!(world.entities()[0].pos.z, 2.);
+    let items = world.remove_entities(|e| matches!(e.kind, EntityKind::Item(_)));
+    assert_eq!(items.len(), 1);
+    assert!(world.entities().is_empty());
+}
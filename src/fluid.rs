@@ -0,0 +1,150 @@
+/****************************************
+   Fluids
+   water and lava flow block by block through
+   scheduled updates: down first, then sideways losing
+   some of their level for every block they
+   travel, flowing fluid dries up once nothing
+   feeds it anymore
+****************************************/
+use macroquad::math::IVec3;
+
+use crate::{
+    chunk::chunk_of,
+    registry::{BlockId, AIR},
+    state::BlockState,
+    world::World,
+};
+
+/// level of a fluid filling it's whole block, sources and falling fluid are always full
+pub const FULL: u8 = 7;
+/// directions fluid spreads in once it can't fall any further
+const SIDES: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y];
+
+/// how a fluid block flows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FluidDef {
+    /// levels lost for every block it flows sideways
+    pub falloff: u8,
+}
+
+/// level of a fluid state, sources keep no level of their own
+pub fn fluid_level(state: BlockState) -> u8 {
+    if state.is_flowing() {
+        state.level()
+    } else {
+        FULL
+    }
+}
+/// how much of it's block a fluid fills (0-1)
+pub fn fluid_height(state: BlockState) -> f32 {
+    (fluid_level(state) + 1) as f32 / (FULL + 1) as f32
+}
+/// a flowing fluid at a level
+fn flowing(id: BlockId, level: u8) -> BlockState {
+    BlockState::new(id).with_level(level).with_flowing(true)
+}
+
+impl World {
+    /// whether fluid can flow into a block, washing away what was there
+    fn holds_fluid(&self, state: BlockState) -> bool {
+        let def = self.registry().get(state.id);
+        !def.solid && def.fluid.is_none()
+    }
+    /// whether fluid at a position falls instead of spreading sideways
+    fn can_fall(&self, pos: IVec3, id: BlockId) -> bool {
+        let below = pos - IVec3::Z;
+        let s = self.get_state(below);
+        World::in_bounds(below) && (self.holds_fluid(s) || (s.id == id && s.is_flowing()))
+    }
+    /// the state a block flows to next, `None` if it stays as it is
+    fn flow(&self, pos: IVec3) -> Option<BlockState> {
+        let current = self.get_state(pos);
+        let is_fluid = self.registry().is_fluid(current.id);
+        // sources never change and solid blocks hold fluid back
+        if (is_fluid && !current.is_flowing()) || (!is_fluid && !self.holds_fluid(current)) {
+            return None;
+        }
+        // different fluids don't mix
+        let feeds = |id: BlockId| self.registry().is_fluid(id) && (!is_fluid || id == current.id);
+        let above = self.get_block(pos + IVec3::Z);
+        let fed = if feeds(above) {
+            Some(flowing(above, FULL))
+        } else {
+            SIDES
+                .iter()
+                .filter_map(|d| {
+                    let n = pos + *d;
+                    let s = self.get_state(n);
+                    let fluid = self.registry().get(s.id).fluid?;
+                    (feeds(s.id) && !self.can_fall(n, s.id))
+                        .then(|| (s.id, fluid_level(s).saturating_sub(fluid.falloff)))
+                })
+                .filter(|(_, level)| *level > 0)
+                .max_by_key(|(_, level)| *level)
+                .map(|(id, level)| flowing(id, level))
+        };
+        match fed {
+            Some(s) => (s != current).then_some(s),
+            None if is_fluid => Some(BlockState::AIR),
+            None => None,
+        }
+    }
+}
+
+/// a fluid's update, it settles it's own level and flows into the blocks around it,
+/// every block that changes schedules the ones next to it so the flow carries on
+pub fn flow_update(world: &mut World, pos: IVec3, _state: BlockState) {
+    if let Some(next) = world.flow(pos) {
+        world.set_state(pos, next).unwrap();
+        if next.id == AIR {
+            return;
+        }
+    }
+    for d in [IVec3::NEG_Z, SIDES[0], SIDES[1], SIDES[2], SIDES[3]] {
+        let n = pos + d;
+        // chunks that aren't loaded hold the fluid back until they are
+        if !World::in_bounds(n) || !world.is_loaded(chunk_of(n)) {
+            continue;
+        }
+        if let Some(next) = world.flow(n) {
+            world.set_state(n, next).unwrap();
+        }
+    }
+}
+#[test]
+fn fluid_test() {
+    use crate::registry::{LAVA, STONE, WATER};
+    use macroquad::math::ivec3;
+    let mut world = World::new();
+    world.fill(ivec3(0, 0, 0), ivec3(15, 15, 0), STONE).unwrap();
+    let settle = |world: &mut World| {
+        while !world.scheduled.is_empty() {
+            world.tick();
+        }
+    };
+    let source = ivec3(8, 8, 3);
+    world.set_block(source, WATER).unwrap();
+    // falls down first and doesn't spread on the way
+    while world.get_block(source - IVec3::Z) == AIR {
+        world.tick();
+    }
+    assert_eq!(world.get_state(source - IVec3::Z), flowing(WATER, FULL));
+    assert_eq!(world.get_block(source + IVec3::X), AIR);
+    settle(&mut world);
+    assert_eq!(world.get_block(source + IVec3::X), AIR);
+    // then spreads out on the floor losing a level with every block
+    for i in 0..FULL {
+        let s = world.get_state(ivec3(8 + i as i32, 8, 1));
+        assert_eq!(fluid_level(s), FULL - i, "{i} blocks away");
+    }
+    assert_eq!(world.get_block(ivec3(15, 8, 1)), AIR);
+    // and dries up once the source is gone
+    world.set_block(source, AIR).unwrap();
+    settle(&mut world);
+    assert_eq!(world.blocks_in(ivec3(0, 0, 1), ivec3(15, 15, 4)).count(), 0);
+    // lava is slower and doesn't get as far
+    world.set_block(ivec3(8, 8, 1), LAVA).unwrap();
+    settle(&mut world);
+    assert_eq!(fluid_level(world.get_state(ivec3(11, 8, 1))), 1);
+    assert_eq!(world.get_block(ivec3(12, 8, 1)), AIR);
+}
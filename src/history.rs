@@ -0,0 +1,155 @@
+/****************************************
+   Edit History
+   remembers what the player changed in the
+   world so it can be undone and redone, every
+   click or tool stroke is one step
+****************************************/
+use std::collections::VecDeque;
+
+use crate::{
+    chunk::chunk_of,
+    world::{BlockChange, World, WorldError},
+};
+
+/// undo and redo stacks of block edits
+pub struct History {
+    undo: VecDeque<Vec<BlockChange>>,
+    redo: Vec<Vec<BlockChange>>,
+    /// the step edits are added to while a stroke is going on
+    stroke: Option<Vec<BlockChange>>,
+    /// how many steps are kept, the oldest are forgotten first
+    pub limit: usize,
+}
+impl History {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            stroke: None,
+            limit,
+        }
+    }
+    /// runs `f` on the world and remembers every block it changed as one step,
+    /// or as part of the current stroke if one was started
+    pub fn edit<T>(&mut self, world: &mut World, f: impl FnOnce(&mut World) -> T) -> T {
+        let tracking = world.is_tracking_changes();
+        if !tracking {
+            world.track_changes(true);
+        }
+        let start = world.pending_changes().len();
+        let out = f(world);
+        let changes = world.pending_changes()[start..].to_vec();
+        if !tracking {
+            world.track_changes(false);
+        }
+        match &mut self.stroke {
+            Some(stroke) => stroke.extend(changes),
+            None => self.commit(changes),
+        }
+        out
+    }
+    /// groups every edit until [`History::end_stroke`] into a single step
+    pub fn begin_stroke(&mut self) {
+        self.end_stroke();
+        self.stroke = Some(Vec::new());
+    }
+    pub fn end_stroke(&mut self) {
+        if let Some(stroke) = self.stroke.take() {
+            self.commit(stroke);
+        }
+    }
+    fn commit(&mut self, changes: Vec<BlockChange>) {
+        if changes.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push_back(changes);
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+    /// reverts the last step, false if there was nothing to undo,
+    /// a step reaching into chunks that aren't loaded is left for later
+    pub fn undo(&mut self, world: &mut World) -> Result<bool, WorldError> {
+        self.end_stroke();
+        let Some(step) = self.undo.back() else {
+            return Ok(false);
+        };
+        check_loaded(world, step)?;
+        let step = self.undo.pop_back().unwrap();
+        for change in step.iter().rev() {
+            world.set_state(change.pos, change.old).unwrap();
+        }
+        self.redo.push(step);
+        Ok(true)
+    }
+    /// applies the last undone step again, false if there was nothing to redo,
+    /// a step reaching into chunks that aren't loaded is left for later
+    pub fn redo(&mut self, world: &mut World) -> Result<bool, WorldError> {
+        self.end_stroke();
+        let Some(step) = self.redo.last() else {
+            return Ok(false);
+        };
+        check_loaded(world, step)?;
+        let step = self.redo.pop().unwrap();
+        for change in step.iter() {
+            world.set_state(change.pos, change.new).unwrap();
+        }
+        self.undo.push_back(step);
+        Ok(true)
+    }
+    /// forgets everything, for when the world is replaced
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.stroke = None;
+    }
+}
+/// fails on the first change of a step in a chunk that isn't loaded
+fn check_loaded(world: &World, step: &[BlockChange]) -> Result<(), WorldError> {
+    match step.iter().find(|c| !world.is_loaded(chunk_of(c.pos))) {
+        Some(c) => Err(WorldError::NotLoaded(chunk_of(c.pos))),
+        None => Ok(()),
+    }
+}
+impl Default for History {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+#[test]
+fn undo_redo_test() {
+    use macroquad::math::ivec3;
+    let mut world = World::new();
+    let mut history = History::new(2);
+    let (a, b) = (ivec3(0, 0, 0), ivec3(1, 0, 0));
+    history.edit(&mut world, |w| w.set_block(a, 1)).unwrap();
+    history.begin_stroke();
+    history.edit(&mut world, |w| w.set_block(a, 2)).unwrap();
+    history.edit(&mut world, |w| w.set_block(b, 2)).unwrap();
+    history.end_stroke();
+    assert_eq!(history.undo(&mut world), Ok(true));
+    assert_eq!((world.get_block(a), world.get_block(b)), (1, 0));
+    assert_eq!(history.redo(&mut world), Ok(true));
+    assert_eq!((world.get_block(a), world.get_block(b)), (2, 2));
+    assert_eq!(history.redo(&mut world), Ok(false));
+    // only the last 2 steps are kept
+    history.edit(&mut world, |w| w.set_block(b, 3)).unwrap();
+    assert_eq!(history.undo(&mut world), Ok(true));
+    assert_eq!(history.undo(&mut world), Ok(true));
+    assert_eq!(history.undo(&mut world), Ok(false));
+    assert_eq!((world.get_block(a), world.get_block(b)), (1, 0));
+    // a new edit drops what could be redone
+    history.edit(&mut world, |w| w.set_block(b, 4)).unwrap();
+    assert_eq!(history.redo(&mut world), Ok(false));
+    // steps in chunks that aren't loaded wait until they're back
+    world.set_streamed(true);
+    let (chunk, _) = world.remove_chunk(chunk_of(b)).unwrap();
+    assert_eq!(
+        history.undo(&mut world),
+        Err(WorldError::NotLoaded(chunk_of(b)))
+    );
+    world.insert_chunk(chunk_of(b), chunk);
+    assert_eq!(history.undo(&mut world), Ok(true));
+    assert_eq!(world.get_block(b), 0);
+}
@@ -0,0 +1,289 @@
+/****************************************
+   Lighting
+   every block keeps two light levels (0-15):
+   sky light falling down from the top of the
+   world and block light given off by blocks
+   like machines, both spread by flood fill
+   losing a level for every block they travel
+****************************************/
+use std::collections::VecDeque;
+
+use macroquad::math::{ivec3, IVec2, IVec3};
+
+use crate::{
+    chunk::{chunk_of, local_of, world_of, CHUNK_SIZE, HEIGHT},
+    state::BlockState,
+    world::{World, NEIGHBORS},
+};
+
+/// the brightest light can get
+pub const MAX_LIGHT: u8 = 15;
+
+/// how bright something is drawn at a light level, the darkest blocks stay a little visible
+pub fn brightness(level: u8) -> f32 {
+    const DARKEST: f32 = 0.12;
+    let l = level.min(MAX_LIGHT) as f32 / MAX_LIGHT as f32;
+    DARKEST + (1. - DARKEST) * l.powf(1.5)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightChannel {
+    Sky,
+    Block,
+}
+impl LightChannel {
+    #[inline]
+    fn get(self, packed: u8) -> u8 {
+        match self {
+            LightChannel::Sky => packed >> 4,
+            LightChannel::Block => packed & 0xF,
+        }
+    }
+    #[inline]
+    fn set(self, packed: u8, level: u8) -> u8 {
+        match self {
+            LightChannel::Sky => (packed & 0xF) | (level << 4),
+            LightChannel::Block => (packed & 0xF0) | level,
+        }
+    }
+}
+
+impl World {
+    /// light of one channel at a position, `None` where no chunk is loaded
+    fn channel_light(&self, channel: LightChannel, pos: IVec3) -> Option<u8> {
+        if !World::in_bounds(pos) {
+            return None;
+        }
+        self.chunk(chunk_of(pos))
+            .map(|c| channel.get(c.light(local_of(pos))))
+    }
+    fn set_channel_light(&mut self, channel: LightChannel, pos: IVec3, level: u8) {
+        let Some(c) = self.chunk_mut(chunk_of(pos)) else {
+            return;
+        };
+        let local = local_of(pos);
+        let packed = channel.set(c.light(local), level);
+        if c.light(local) != packed {
+            c.set_light(local, packed);
+            self.light_changed(pos);
+        }
+    }
+    /// sky light at a position, full above the world and where nothing is loaded
+    pub fn sky_light(&self, pos: IVec3) -> u8 {
+        if pos.z < 0 {
+            return 0;
+        }
+        self.channel_light(LightChannel::Sky, pos)
+            .unwrap_or(MAX_LIGHT)
+    }
+    /// light given off by blocks at a position
+    pub fn block_light(&self, pos: IVec3) -> u8 {
+        self.channel_light(LightChannel::Block, pos).unwrap_or(0)
+    }
+    /// the brighter of both lights at a position
+    pub fn light_at(&self, pos: IVec3) -> u8 {
+        self.sky_light(pos).max(self.block_light(pos))
+    }
+    fn blocks_light(&self, pos: IVec3) -> bool {
+        !self.registry().is_transparent(self.get_block(pos))
+    }
+    /// lights up every block of the world from scratch
+    pub(crate) fn relight(&mut self) {
+        let chunks: Vec<IVec2> = self.chunks().map(|(c, _)| c).collect();
+        let mut sky = VecDeque::new();
+        let mut block = VecDeque::new();
+        for c in &chunks {
+            self.chunk_mut(*c).unwrap().clear_light();
+            self.seed_light(*c, &mut sky, &mut block);
+        }
+        self.spread_light(LightChannel::Sky, sky);
+        self.spread_light(LightChannel::Block, block);
+        // most of the sky and the rock below ends up lit the same all over
+        for c in chunks {
+            self.chunk_mut(c).unwrap().compact();
+        }
+    }
+    /// lights up a single chunk that was just put into the world
+    pub(crate) fn relight_chunk(&mut self, chunk: IVec2) {
+        let mut sky = VecDeque::new();
+        let mut block = VecDeque::new();
+        self.chunk_mut(chunk).unwrap().clear_light();
+        self.seed_light(chunk, &mut sky, &mut block);
+        // let the light of the neighbours flow in over the borders
+        for y in -1..=CHUNK_SIZE {
+            for x in -1..=CHUNK_SIZE {
+                let border = x == -1 || y == -1 || x == CHUNK_SIZE || y == CHUNK_SIZE;
+                let corner = (x == -1 || x == CHUNK_SIZE) && (y == -1 || y == CHUNK_SIZE);
+                if border && !corner {
+                    for z in 0..HEIGHT {
+                        let pos = world_of(chunk, ivec3(x, y, z));
+                        sky.push_back(pos);
+                        block.push_back(pos);
+                    }
+                }
+            }
+        }
+        self.spread_light(LightChannel::Sky, sky);
+        self.spread_light(LightChannel::Block, block);
+        self.chunk_mut(chunk).unwrap().compact();
+    }
+    /// sets the sky light of open columns and the light of glowing blocks of a chunk
+    fn seed_light(&mut self, chunk: IVec2, sky: &mut VecDeque<IVec3>, block: &mut VecDeque<IVec3>) {
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let mut open = true;
+                for z in (0..HEIGHT).rev() {
+                    let pos = world_of(chunk, ivec3(x, y, z));
+                    let def = self.registry().get(self.get_block(pos));
+                    let emission = def.light_emission.min(MAX_LIGHT);
+                    open &= def.transparent;
+                    if open {
+                        self.set_channel_light(LightChannel::Sky, pos, MAX_LIGHT);
+                        sky.push_back(pos);
+                    }
+                    if emission > 0 {
+                        self.set_channel_light(LightChannel::Block, pos, emission);
+                        block.push_back(pos);
+                    }
+                }
+            }
+        }
+    }
+    /// flood fills light out of the queued positions
+    fn spread_light(&mut self, channel: LightChannel, mut queue: VecDeque<IVec3>) {
+        while let Some(pos) = queue.pop_front() {
+            let Some(level) = self.channel_light(channel, pos).filter(|l| *l > 0) else {
+                continue;
+            };
+            for d in NEIGHBORS {
+                let n = pos + d;
+                if self.blocks_light(n) {
+                    continue;
+                }
+                // sky light going straight down doesn't fade
+                let next =
+                    if channel == LightChannel::Sky && d == IVec3::NEG_Z && level == MAX_LIGHT {
+                        MAX_LIGHT
+                    } else {
+                        level - 1
+                    };
+                if self.channel_light(channel, n).is_some_and(|l| l < next) {
+                    self.set_channel_light(channel, n, next);
+                    queue.push_back(n);
+                }
+            }
+        }
+    }
+    /// darkens everything lit from the queued positions (with the level they had)
+    /// and returns the brighter positions around that have to spread their light again
+    fn unspread_light(
+        &mut self,
+        channel: LightChannel,
+        mut queue: VecDeque<(IVec3, u8)>,
+    ) -> VecDeque<IVec3> {
+        let mut relight = VecDeque::new();
+        while let Some((pos, level)) = queue.pop_front() {
+            for d in NEIGHBORS {
+                let n = pos + d;
+                let Some(l) = self.channel_light(channel, n).filter(|l| *l > 0) else {
+                    continue;
+                };
+                let below_sky =
+                    channel == LightChannel::Sky && d == IVec3::NEG_Z && level == MAX_LIGHT;
+                if l < level || below_sky {
+                    self.set_channel_light(channel, n, 0);
+                    queue.push_back((n, l));
+                } else {
+                    relight.push_back(n);
+                }
+            }
+        }
+        relight
+    }
+    /// keeps the light around a block right after it changed from `old` to `new`
+    pub(crate) fn update_light(&mut self, pos: IVec3, old: BlockState, new: BlockState) {
+        let (old, new) = (self.registry().get(old.id), self.registry().get(new.id));
+        if old.transparent == new.transparent && old.light_emission == new.light_emission {
+            return;
+        }
+        let (transparent, emission) = (new.transparent, new.light_emission.min(MAX_LIGHT));
+        for channel in [LightChannel::Sky, LightChannel::Block] {
+            let mut removed = VecDeque::new();
+            if let Some(level) = self.channel_light(channel, pos).filter(|l| *l > 0) {
+                self.set_channel_light(channel, pos, 0);
+                removed.push_back((pos, level));
+            }
+            let mut queue = self.unspread_light(channel, removed);
+            if channel == LightChannel::Block && emission > 0 {
+                self.set_channel_light(channel, pos, emission);
+                queue.push_back(pos);
+            }
+            if transparent {
+                if channel == LightChannel::Sky && pos.z == HEIGHT - 1 {
+                    self.set_channel_light(channel, pos, MAX_LIGHT);
+                    queue.push_back(pos);
+                }
+                // light around flows into the opened up space
+                queue.extend(NEIGHBORS.iter().map(|d| pos + *d));
+            }
+            self.spread_light(channel, queue);
+        }
+    }
+}
+#[test]
+fn light_test() {
+    use crate::registry::{MACHINE, STONE};
+    let mut world = World::new();
+    world.fill(ivec3(0, 0, 0), ivec3(15, 15, 0), STONE).unwrap();
+    world.enable_lighting();
+    assert_eq!(world.sky_light(ivec3(3, 3, 1)), MAX_LIGHT);
+    assert_eq!(world.sky_light(ivec3(3, 3, 0)), 0);
+    // a roof darkens what's under it, light comes in from the sides
+    world.fill(ivec3(2, 2, 3), ivec3(6, 6, 3), STONE).unwrap();
+    assert_eq!(world.sky_light(ivec3(4, 4, 2)), MAX_LIGHT - 3);
+    assert_eq!(world.sky_light(ivec3(4, 4, 4)), MAX_LIGHT);
+    // glowing blocks light up their surroundings
+    let machine = world.registry().get(MACHINE).light_emission;
+    world.set_block(ivec3(4, 4, 1), MACHINE).unwrap();
+    assert_eq!(world.block_light(ivec3(4, 4, 2)), machine - 1);
+    assert_eq!(world.block_light(ivec3(4, 7, 1)), machine - 3);
+    // and it goes away with them
+    world.set_block(ivec3(4, 4, 1), 0).unwrap();
+    assert_eq!(world.block_light(ivec3(4, 4, 2)), 0);
+    assert_eq!(world.block_light(ivec3(4, 7, 1)), 0);
+    // taking the roof off lets the sky back in
+    world.fill(ivec3(2, 2, 3), ivec3(6, 6, 3), 0).unwrap();
+    assert_eq!(world.sky_light(ivec3(4, 4, 2)), MAX_LIGHT);
+    // chunks created by placing a block get lit up too
+    world.set_block(ivec3(40, 0, 3), STONE).unwrap();
+    assert_eq!(world.sky_light(ivec3(41, 0, 0)), MAX_LIGHT);
+    assert_eq!(world.sky_light(ivec3(40, 0, 2)), MAX_LIGHT - 1);
+    // incremental updates end up where lighting from scratch does
+    world.fill(ivec3(0, 0, 5), ivec3(9, 9, 5), STONE).unwrap();
+    world.set_block(ivec3(1, 1, 2), MACHINE).unwrap();
+    world.set_block(ivec3(9, 9, 5), 0).unwrap();
+    let lights: Vec<_> = world
+        .blocks_in(ivec3(0, 0, 0), ivec3(15, 15, 8))
+        .map(|(p, _)| {
+            (
+                world.sky_light(p + IVec3::Z),
+                world.block_light(p + IVec3::Z),
+            )
+        })
+        .collect();
+    world.relight();
+    let fresh: Vec<_> = world
+        .blocks_in(ivec3(0, 0, 0), ivec3(15, 15, 8))
+        .map(|(p, _)| {
+            (
+                world.sky_light(p + IVec3::Z),
+                world.block_light(p + IVec3::Z),
+            )
+        })
+        .collect();
+    assert_eq!(lights, fresh);
+    // changes to the light are recorded along with the block changes
+    world.track_changes(true);
+    world.set_block(ivec3(12, 12, 6), STONE).unwrap();
+    assert!(world.take_light_changes().contains(&ivec3(12, 12, 5)));
+}
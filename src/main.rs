@@ -1,32 +1,64 @@
 pub mod math;
 pub mod render;
 
+use entity::EntityKind;
+use history::History;
 use math::*;
 use miniquad::{window::screen_size, BlendState};
 use objects::*;
+use region::RegionStore;
+use registry::{BlockId, BlockRegistry, AIR, GRASS};
 use render::*;
+use save::SaveError;
+use state::{BlockState, Facing};
+use streaming::{ChunkStreamer, StreamUpdate};
+use structure::{Mirror, Rotation, Structure};
 use world::World;
+use worldgen::TerrainGenerator;
 
-use macroquad::{
-    material,
-    prelude::*,
-    time,
-    ui::{
-        widgets::{Button, Group, Window},
-        *,
-    },
-};
+use macroquad::{material, prelude::*, ui::*};
 use std::{
     cell::{Ref, RefCell, RefMut},
     cmp::Ordering,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    iter,
     rc::Rc,
 };
 
+mod biome;
+mod block_entity;
+mod carving;
+mod chunk;
+mod edit;
+mod entity;
+mod fluid;
+mod history;
+mod light;
+mod noise;
 mod objects;
+mod occlusion;
+mod picking;
+mod power;
+mod region;
+mod registry;
+mod save;
+mod section;
+mod state;
+mod streaming;
+mod structure;
+mod update;
 mod world;
+mod worldgen;
 mod constants {
     pub const TILE_SIZE: (f32, f32) = (64.0, 64.0);
+    /// how far from the player blocks can be broken and placed
+    pub const REACH: f32 = 6.0;
+    /// world ticks per second, the world runs at this pace no matter the frame rate
+    pub const TICKS_PER_SECOND: f64 = 20.;
+    /// the most ticks run in one frame to catch up, anything longer behind is skipped
+    pub const MAX_CATCH_UP: u32 = 10;
+    /// the most chunks loaded or generated in one frame, the rest follow in the next ones
+    pub const CHUNK_LOADS_PER_FRAME: usize = 1;
 }
 use constants::*;
 
@@ -42,32 +74,213 @@ pub(crate) enum PlayerOrient {
     _315 = 315,
 }
 pub(crate) struct Game {
-    block_trans_map: Vec<u32>,
     block_textures: Vec<Texture2D>,
     player_textures: HashMap<PlayerOrient, Texture2D>,
     player_object: Rc<RefCell<Player>>,
     world: World,
     debug: bool,
     draw_queue: Vec<Rc<RefCell<dyn Renderble>>>,
+    /// the blocks of the draw queue by their position, to find them again when they change
+    block_objects: HashMap<IVec3, Rc<RefCell<Block>>>,
     block_material: Material,
     blocks_cover_player: bool,
-    selected_id: u32,
-    shade_top: Texture2D,
-    shade_bot: Texture2D,
+    selected_id: BlockId,
     selection_top: Texture2D,
     ui_selection_mode: bool,
     ui_selection_pos: Vec2,
+    /// a message shown to the player until the given time
+    message: Option<(String, f64)>,
+    /// whether clicks select a region for the bulk editing keys instead of placing blocks
+    tool_mode: bool,
+    /// the block being broken and for how long, see [`registry::BlockDef::hardness`]
+    breaking: Option<(IVec3, f32)>,
+    /// the two corners of the selected region
+    selection: [Option<IVec3>; 2],
+    /// the last copied region and how it's flipped when pasted
+    clipboard: Option<Structure>,
+    paste_mirror: Option<Mirror>,
+    /// what the player changed, to undo and redo it
+    history: History,
+    /// when the next world tick is due, see [`Game::run_ticks`]
+    next_tick: f64,
+    /// the drawn entities of the world, replaced every frame
+    entity_objects: Vec<Rc<RefCell<EntitySprite>>>,
+    /// templates the player can place, the selected one and how it's turned
+    structures: Vec<Structure>,
+    selected_structure: usize,
+    structure_rotation: Rotation,
+    /// loads the chunks around the player and puts away the ones far from them
+    streamer: ChunkStreamer<TerrainGenerator>,
     // buffer_queue: Vec<Rc<RefCell<dyn ISOGraphics>>>, // todo: a buffer for holding old data in draw queue to be moved out or into draw queue on player discovery of new visible chunk
 }
 impl Game {
-    fn player(&self) -> Ref<Player> {
+    fn player(&self) -> Ref<'_, Player> {
         self.player_object.as_ref().borrow()
     }
-    fn player_mut(&self) -> RefMut<Player> {
+    fn player_mut(&self) -> RefMut<'_, Player> {
         self.player_object.as_ref().borrow_mut()
     }
+    /// moves the selected block forward or backward trough the placeable blocks
+    fn cycle_selected(&mut self, step: i32) {
+        let placeable: Vec<BlockId> = self.world.registry().placeable().collect();
+        let i = placeable
+            .iter()
+            .position(|id| *id == self.selected_id)
+            .unwrap_or(0) as i32;
+        self.selected_id = placeable[(i + step).rem_euclid(placeable.len() as i32) as usize];
+    }
+    /// refills the draw queue with the player and every block of the loaded chunks
+    fn reload_draw_queue(&mut self) {
+        self.draw_queue.clear();
+        self.block_objects.clear();
+        self.draw_queue.push(self.player_object.clone());
+        for (pos, state) in self.world.blocks() {
+            // hidden blocks are never drawn, no need to sort them every frame
+            if !self.world.is_exposed(pos) {
+                continue;
+            }
+            let block = Rc::new(RefCell::new(objects::Block::new(
+                pos.as_vec3(),
+                state,
+                &self.world,
+            )));
+            self.block_objects.insert(pos, block.clone());
+            self.draw_queue.push(block);
+        }
+        // the queue matches the world now, from here on only follow what changes
+        self.world.track_changes(true);
+    }
+    /// updates only the blocks of the draw queue touched by changes since the last update
+    fn apply_world_changes(&mut self) {
+        let changes = self.world.take_changes();
+        let light = self.world.take_light_changes();
+        if changes.is_empty() && light.is_empty() {
+            return;
+        }
+        // a block showing up or going away can hide or uncover the blocks behind it
+        // and darkens the corners of the faces around it
+        let touched = changes
+            .iter()
+            .flat_map(|c| iter::once(c.pos).chain(occlusion::shaded_by(c.pos)))
+            // blocks are lit by the light in front of their visible faces
+            .chain(
+                light
+                    .iter()
+                    .flat_map(|p| [*p - IVec3::X, *p - IVec3::Y, *p - IVec3::Z]),
+            )
+            .collect();
+        self.refresh_blocks(touched);
+    }
+    /// adds, updates or drops the blocks of the draw queue at `positions` to match the world
+    fn refresh_blocks(&mut self, mut positions: Vec<IVec3>) {
+        positions.sort_by_key(|p| p.to_array());
+        positions.dedup();
+        let mut removed = HashSet::new();
+        for pos in positions {
+            let state = self.world.get_state(pos);
+            let visible = state.id != AIR && self.world.is_exposed(pos);
+            match (self.block_objects.get(&pos), visible) {
+                (Some(block), true) => block.borrow_mut().update(state, &self.world),
+                (None, true) => {
+                    let block = Rc::new(RefCell::new(objects::Block::new(
+                        pos.as_vec3(),
+                        state,
+                        &self.world,
+                    )));
+                    self.block_objects.insert(pos, block.clone());
+                    self.draw_queue.push(block);
+                }
+                (Some(_), false) => {
+                    let block = self.block_objects.remove(&pos).unwrap();
+                    removed.insert(Rc::as_ptr(&block) as *const ());
+                }
+                (None, false) => {}
+            }
+        }
+        if !removed.is_empty() {
+            self.draw_queue
+                .retain(|r| !removed.contains(&(Rc::as_ptr(r) as *const ())));
+        }
+    }
+    /// loads and unloads chunks as the player moves and brings the draw queue along
+    fn stream_chunks(&mut self) {
+        let center = chunk::chunk_of(self.player().pos().floor().as_ivec3());
+        let update = match self
+            .streamer
+            .update(&mut self.world, center, CHUNK_LOADS_PER_FRAME)
+        {
+            Ok(update) => update,
+            Err(e) => {
+                self.notify(format!("Couldn't stream chunks: {e}"));
+                return;
+            }
+        };
+        let StreamUpdate { loaded, unloaded } = update;
+        let unloaded: HashSet<IVec2> = unloaded.into_iter().collect();
+        if !unloaded.is_empty() {
+            let removed: HashSet<*const ()> = self
+                .block_objects
+                .extract_if(|p, _| unloaded.contains(&chunk::chunk_of(*p)))
+                .map(|(_, b)| Rc::as_ptr(&b) as *const ())
+                .collect();
+            self.draw_queue
+                .retain(|r| !removed.contains(&(Rc::as_ptr(r) as *const ())));
+        }
+        // every block of a loaded chunk, and the edges of the chunks behind the ones
+        // that came or went as the blocks there were hidden or shaded by them
+        let mut positions = Vec::new();
+        for chunk in loaded.iter().chain(&unloaded) {
+            let origin = chunk::world_of(*chunk, IVec3::ZERO);
+            for z in 0..chunk::HEIGHT {
+                for i in -1..chunk::CHUNK_SIZE {
+                    positions.push(origin + ivec3(-1, i, z));
+                    positions.push(origin + ivec3(i, -1, z));
+                }
+            }
+        }
+        positions.retain(|p| !unloaded.contains(&chunk::chunk_of(*p)));
+        for chunk in &loaded {
+            positions.extend(self.world.chunk_blocks(*chunk).map(|(p, _)| p));
+        }
+        self.refresh_blocks(positions);
+    }
+    /// runs the world ticks due by now, they're spaced by the clock and not the frames
+    fn run_ticks(&mut self) {
+        let now = get_time();
+        let tick = 1. / TICKS_PER_SECOND;
+        // after a long stall (like a dragged window) skip ahead instead of catching up
+        if now - self.next_tick > tick * MAX_CATCH_UP as f64 {
+            self.next_tick = now;
+        }
+        while self.next_tick <= now {
+            self.world.tick();
+            self.next_tick += tick;
+        }
+    }
+    /// puts the entities of the world in the draw queue in place of the ones from the last frame
+    fn sync_entities(&mut self) {
+        let old: HashSet<*const ()> = self
+            .entity_objects
+            .drain(..)
+            .map(|e| Rc::as_ptr(&e) as *const ())
+            .collect();
+        if !old.is_empty() {
+            self.draw_queue
+                .retain(|r| !old.contains(&(Rc::as_ptr(r) as *const ())));
+        }
+        for e in self.world.entities() {
+            let sprite = Rc::new(RefCell::new(EntitySprite::new(e.pos, e.kind)));
+            self.draw_queue.push(sprite.clone());
+            self.entity_objects.push(sprite);
+        }
+    }
+    /// show a message to the player for a few seconds
+    fn notify(&mut self, message: String) {
+        self.message = Some((message, get_time() + 3.));
+    }
 }
 #[inline]
+#[allow(dead_code)]
 fn cmp_tiles(lhs: Vec3, rhs: Vec3) -> Ordering {
     (lhs.x + lhs.y + lhs.z)
         .partial_cmp(&(rhs.x + rhs.y + rhs.z))
@@ -95,6 +308,7 @@ fn in_2d(pos: Vec3) -> Vec2 {
         .with_y(pp.y + TILE_SIZE.1 / 2.)
 }
 /// tests if a block exists on screen (not necesserly visible)
+#[allow(dead_code)]
 fn is_on_screen(pos: Vec3, cam: &Camera2D) -> bool {
     let r = Rect::new(0., 0., screen_width(), screen_width());
     let f = flatten_iso(pos);
@@ -164,75 +378,173 @@ fn load_player_assets() -> HashMap<PlayerOrient, Texture2D> {
         .for_each(|f| f.1.set_filter(FilterMode::Nearest));
     _player_textures
 }
-async fn load_tiles_assets() -> Vec<Texture2D> {
-    let mut tiles: Vec<Texture2D> = Vec::new();
-    tiles.push(Texture2D::from_file_with_format(
-        include_bytes!("../empty.png"),
-        Some(ImageFormat::Png),
-    ));
-    // tiles.push(Texture2D::from_file_with_format(
-    //     include_bytes!("../tile_select.png"),
-    //     Some(ImageFormat::Png),
-    // ));
-    // tiles.push(Texture2D::from_file_with_format(
-    //     include_bytes!("../tile_frame.png"),
-    //     Some(ImageFormat::Png),
-    // ));
-    tiles.push(Texture2D::from_file_with_format(
-        include_bytes!("../tile_stone.png"),
-        Some(ImageFormat::Png),
-    ));
-    tiles.push(Texture2D::from_file_with_format(
-        include_bytes!("../tile_dirt.png"),
-        Some(ImageFormat::Png),
-    ));
-    tiles.push(Texture2D::from_file_with_format(
-        include_bytes!("../tile_grass.png"),
-        Some(ImageFormat::Png),
-    ));
-    tiles.push(Texture2D::from_file_with_format(
-        include_bytes!("../tile_stone_smooth.png"),
-        Some(ImageFormat::Png),
-    ));
-    tiles.push(Texture2D::from_file_with_format(
-        include_bytes!("../tile.png"),
-        Some(ImageFormat::Png),
-    ));
-    tiles.push(Texture2D::from_file_with_format(
-        include_bytes!("../tile_gravel.png"),
-        Some(ImageFormat::Png),
-    ));
-    tiles.push(Texture2D::from_file_with_format(
-        include_bytes!("../tile_machine.png"),
-        Some(ImageFormat::Png),
-    ));
-    // tles.push(load_texture("tile_machine.png").await.unwrap());
+/// loads the tile of every registered block, indexed by block id
+fn load_tiles_assets(registry: &BlockRegistry) -> Vec<Texture2D> {
+    let tiles: Vec<Texture2D> = registry
+        .iter()
+        .map(|(_, def)| Texture2D::from_file_with_format(def.texture, Some(ImageFormat::Png)))
+        .collect();
     for tile in &tiles {
         tile.set_filter(FilterMode::Nearest);
     }
     tiles
 }
-fn generate_world(world: &mut World) {
-    for i in 0..50 {
-        for j in 0..50 {
-            //ground
-            world.set_block(i, j, 0, 3);
-            // hill
-            // if (5..=8).contains(&i) && (3..=6).contains(&j) {
-            // world.set_block(i, j, 2, 3);
-            // world.set_block(i, j, 4, 3);
-            // }
-            // if i > 0 && i < 3 && j > 0 && j < 3 {
-            // world.set_block(i, j, 4, 3);
-            // }
+/// where the world is saved to and loaded from
+const SAVE_PATH: &str = "world.isow";
+/// where chunks away from the player are kept while playing
+const REGION_DIR: &str = "world.regions";
+/// the region files belonging to the save, copied from and to [`REGION_DIR`] on save and load
+const SAVE_REGION_DIR: &str = "world.isow.regions";
+/// a streamer generating chunks of the world with `seed`
+fn chunk_streamer(seed: u64) -> ChunkStreamer<TerrainGenerator> {
+    ChunkStreamer::new(TerrainGenerator::new(seed), RegionStore::new(REGION_DIR))
+}
+/// starts a new world and returns where the player should spawn
+fn start_world(game: &mut Game, seed: u64) -> Result<Vec3, SaveError> {
+    game.world = World::new();
+    game.world.set_seed(seed);
+    game.world.enable_lighting();
+    game.streamer = chunk_streamer(seed);
+    // chunks of the world played before don't belong to this one
+    if !cfg!(target_arch = "wasm32") {
+        game.streamer.regions_mut().clear()?;
+    }
+    // the chunks around the spawn are there right away, the rest comes in while playing
+    while !game
+        .streamer
+        .update(&mut game.world, IVec2::ZERO, usize::MAX)?
+        .loaded
+        .is_empty()
+    {}
+    // the player stands on the block diagonal to it's position,
+    // the top of the column so caves under it don't count
+    let ground = game.world.top_block(1, 1).solid.map_or(0, |z| z + 1);
+    Ok(vec3(0., 0., ground as f32))
+}
+#[cfg(not(target_arch = "wasm32"))]
+fn save_game(game: &Game) -> Result<(), SaveError> {
+    use std::io::Write;
+    // the chunks that aren't loaded are part of the save too
+    game.streamer
+        .regions()
+        .copy_to(&mut RegionStore::new(SAVE_REGION_DIR))?;
+    let mut file = std::io::BufWriter::new(std::fs::File::create(SAVE_PATH)?);
+    game.world.save(&mut file, game.player().pos())?;
+    file.flush()?;
+    Ok(())
+}
+#[cfg(not(target_arch = "wasm32"))]
+fn load_game(game: &mut Game) -> Result<(), SaveError> {
+    let file = std::io::BufReader::new(std::fs::File::open(SAVE_PATH)?);
+    let (mut world, player_pos) = World::load(file)?;
+    world.enable_lighting();
+    let mut streamer = chunk_streamer(world.seed());
+    // what was unloaded since the save was made goes back to how it was then
+    RegionStore::new(SAVE_REGION_DIR).copy_to(streamer.regions_mut())?;
+    game.streamer = streamer;
+    game.world = world;
+    game.player_mut().set_pos(player_pos);
+    Ok(())
+}
+// todo: browsers have no file system, saves could go to local storage
+#[cfg(target_arch = "wasm32")]
+fn save_game(_game: &Game) -> Result<(), SaveError> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported).into())
+}
+#[cfg(target_arch = "wasm32")]
+fn load_game(_game: &mut Game) -> Result<(), SaveError> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported).into())
+}
+/// bulk editing while in tool mode, `cursor` is the top block under the mouse
+///
+/// click picks the first corner and shift click the second, then
+/// F fills, H builds a hollow box, E replaces the first corner's block,
+/// O makes a sphere and C a cylinder around the first corner, L draws a line,
+/// Ctrl+C/X copy or cut, V pastes at the cursor (turned with R, flipped with M)
+fn use_tools(game: &mut Game, cursor: IVec3) {
+    if is_mouse_button_pressed(MouseButton::Left)
+        && !root_ui().is_mouse_over(vec2(mouse_position().0, mouse_position().1))
+    {
+        let i = is_key_down(miniquad::KeyCode::LeftShift) as usize;
+        game.selection[i] = Some(cursor);
+    }
+    let ctrl = is_key_down(miniquad::KeyCode::LeftControl);
+    let state = BlockState::new(game.selected_id);
+    if is_key_pressed(miniquad::KeyCode::M) {
+        game.paste_mirror = match game.paste_mirror {
+            None => Some(Mirror::X),
+            Some(Mirror::X) => Some(Mirror::Y),
+            Some(Mirror::Y) => None,
+        };
+        game.notify(format!("Paste mirror: {:?}", game.paste_mirror));
+    }
+    if is_key_pressed(miniquad::KeyCode::V) {
+        let Some(clipboard) = &game.clipboard else {
+            game.notify("Nothing to paste".to_string());
+            return;
+        };
+        let structure = match game.paste_mirror {
+            Some(mirror) => clipboard.mirrored(mirror, game.world.registry()),
+            None => clipboard.clone(),
+        };
+        let rotation = game.structure_rotation;
+        let result = game.history.edit(&mut game.world, |w| {
+            structure.place(w, cursor + IVec3::Z, rotation)
+        });
+        if let Err(e) = result {
+            game.notify(format!("Paste didn't fit: {e}"));
+        }
+        return;
+    }
+    let [Some(a), Some(b)] = game.selection else {
+        return;
+    };
+    let world = &mut game.world;
+    let changed = if is_key_pressed(miniquad::KeyCode::F) {
+        game.history.edit(world, |w| w.fill(a, b, state))
+    } else if is_key_pressed(miniquad::KeyCode::H) {
+        game.history.edit(world, |w| w.hollow_box(a, b, state))
+    } else if is_key_pressed(miniquad::KeyCode::E) {
+        let old = world.get_block(a);
+        game.history.edit(world, |w| w.replace(a, b, old, state))
+    } else if is_key_pressed(miniquad::KeyCode::O) {
+        let radius = (b - a).as_vec3().length();
+        game.history
+            .edit(world, |w| w.sphere(a, radius, state, false))
+    } else if is_key_pressed(miniquad::KeyCode::C) && !ctrl {
+        let radius = (b - a).truncate().as_vec2().length();
+        // from the first corner's layer up (or down) to the second's
+        let dz = b.z - a.z;
+        let height = dz + if dz < 0 { -1 } else { 1 };
+        game.history
+            .edit(world, |w| w.cylinder(a, radius, height, state))
+    } else if is_key_pressed(miniquad::KeyCode::L) {
+        game.history.edit(world, |w| w.line(a, b, state))
+    } else if is_key_pressed(miniquad::KeyCode::C) && ctrl {
+        match world.copy(a, b, a) {
+            Ok(copy) => {
+                game.clipboard = Some(copy);
+                game.notify("Copied the selection".to_string());
+            }
+            Err(e) => game.notify(format!("Couldn't copy: {e}")),
+        }
+        return;
+    } else if is_key_pressed(miniquad::KeyCode::X) && ctrl {
+        match game.history.edit(world, |w| w.cut(a, b, a)) {
+            Ok(copy) => {
+                game.clipboard = Some(copy);
+                game.notify("Cut the selection".to_string());
+            }
+            Err(e) => game.notify(format!("Couldn't cut: {e}")),
         }
+        return;
+    } else {
+        return;
+    };
+    match changed {
+        Ok(changed) => game.notify(format!("Changed {changed} blocks")),
+        Err(e) => game.notify(format!("Couldn't edit: {e}")),
     }
-    // world.set_block(10, 10, 1, 6);
-    // world.set_block(11, 10, 1, 6);
-    // world.set_block(12, 10, 1, 6);
-    // world.set_block(10, 10, 3 + 2, 6);
-    // world.set_block(11, 10, 3 + 2, 6);
-    // world.set_block(12, 10, 3 + 2, 6);
 }
 const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
 const BUILD_TIME: Option<&str> = option_env!("SOURCE_DATE_EPOCH");
@@ -241,31 +553,22 @@ async fn main() {
     let _quad_gl = unsafe { get_internal_gl().quad_gl };
     let _quad_context = unsafe { get_internal_gl().quad_context };
     let mut game = Game {
-        block_trans_map: vec![0, 1, 2],
         selection_top: Texture2D::from_file_with_format(
             include_bytes!("../selection_top.png"),
             Some(ImageFormat::Png),
         ),
-
-        shade_top: Texture2D::from_file_with_format(
-            include_bytes!("../shade_top.png"),
-            Some(ImageFormat::Png),
-        ),
-        shade_bot: Texture2D::from_file_with_format(
-            include_bytes!("../shade_bot.png"),
-            Some(ImageFormat::Png),
-        ),
-        selected_id: 3,
+        selected_id: GRASS,
         blocks_cover_player: false,
-        block_textures: load_tiles_assets().await,
+        block_textures: Vec::new(),
         player_object: Rc::new(RefCell::new(objects::Player::new(
             vec3(0., 0., 1.),
             Vec3::ZERO,
         ))),
         world: world::World::new(),
         player_textures: load_player_assets(),
-        debug: if cfg!(debug_assertions) { true } else { false },
+        debug: cfg!(debug_assertions),
         draw_queue: Vec::with_capacity(1000),
+        block_objects: HashMap::new(),
         block_material: material::load_material(
             ShaderSource::Glsl {
                 vertex: include_str!("shader.vs"),
@@ -284,6 +587,12 @@ async fn main() {
                     ("player_hidble".to_string(), UniformType::Int1),
                     ("block_behind_player".to_string(), UniformType::Int1),
                     ("block_over_top".to_string(), UniformType::Int1),
+                    ("tile_origin".to_string(), UniformType::Float2),
+                    ("tile_size".to_string(), UniformType::Float2),
+                    ("tile_cut".to_string(), UniformType::Float1),
+                    ("ao_top".to_string(), UniformType::Float4),
+                    ("ao_left".to_string(), UniformType::Float4),
+                    ("ao_right".to_string(), UniformType::Float4),
                 ],
                 pipeline_params: PipelineParams {
                     depth_write: true,
@@ -306,9 +615,33 @@ async fn main() {
         .unwrap(),
         ui_selection_mode: false,
         ui_selection_pos: Vec2::ZERO,
+        message: None,
+        tool_mode: false,
+        breaking: None,
+        selection: [None; 2],
+        clipboard: None,
+        paste_mirror: None,
+        history: History::default(),
+        next_tick: 0.,
+        entity_objects: Vec::new(),
+        structures: Structure::builtin(),
+        selected_structure: 0,
+        structure_rotation: Rotation::None,
+        streamer: chunk_streamer(0),
     };
+    game.block_textures = load_tiles_assets(game.world.registry());
     build_textures_atlas();
-    generate_world(&mut game.world);
+    match load_game(&mut game) {
+        Ok(()) => info!("loaded world from {SAVE_PATH}"),
+        Err(e) => {
+            let seed = miniquad::date::now() as u64;
+            info!("starting a new world with seed {seed}, couldn't load {SAVE_PATH}: {e}");
+            match start_world(&mut game, seed) {
+                Ok(spawn) => game.player_mut().set_pos(spawn),
+                Err(e) => error!("couldn't load the chunks around the spawn: {e}"),
+            }
+        }
+    }
     let mut camera = Camera2D::from_display_rect(Rect {
         x: -500.,
         y: -500.,
@@ -320,13 +653,7 @@ async fn main() {
     let upper_limit = camera.zoom * 3.;
 
     // let mut draw_queue: Vec<Rc<RefCell<dyn ISOGraphics>>> = Vec::with_capacity(1000);
-    game.draw_queue.push(game.player_object.clone());
-    // unload blocks from storage into render queue
-    // todo: Later do something with dynamic loading where we only load a portion of visible map
-    for ele in game.world.blocks() {
-        game.draw_queue
-            .push(Rc::new(RefCell::new(objects::Block::new(ele.0, ele.1))));
-    }
+    game.reload_draw_queue();
     let mut curser_pos_iso = vec2(0., 0.);
     loop {
         game.block_material.set_uniform("mouse", mouse_position());
@@ -335,6 +662,23 @@ async fn main() {
             "resolution_cam",
             camera.screen_to_world(screen_size().into()),
         );
+        game.stream_chunks();
+        game.run_ticks();
+        game.world.step_entities(get_frame_time());
+        // items the player walks into get picked up
+        let player = game.player_object.clone();
+        let items = game.world.remove_entities(|e| {
+            matches!(e.kind, EntityKind::Item(_))
+                && player.borrow().intersects_block(e.pos.floor().as_ivec3())
+        });
+        for item in items {
+            if let EntityKind::Item(state) = item.kind {
+                let name = game.world.registry().get(state.id).name;
+                game.notify(format!("Picked up {name}"));
+            }
+        }
+        game.sync_entities();
+        game.apply_world_changes();
         game.draw_queue.sort_by(|a, b| {
             let a = a.as_ref().borrow();
             let b = b.as_ref().borrow();
@@ -349,25 +693,13 @@ async fn main() {
             camera.zoom = camera.zoom.clamp(lower_limit, upper_limit);
         } else if mouse_wheel().1.abs() > 0. && is_key_down(miniquad::KeyCode::LeftShift) {
             if mouse_wheel().1.is_sign_positive() {
-                if game.selected_id < (game.block_textures.len() - 1) as u32 {
-                    game.selected_id += 1;
-                } else {
-                    game.selected_id = 1;
-                }
+                game.cycle_selected(1);
             } else {
-                if game.selected_id > 1 as u32 {
-                    game.selected_id -= 1;
-                } else {
-                    game.selected_id = (game.block_textures.len() - 1) as u32;
-                }
+                game.cycle_selected(-1);
             }
         }
         if is_key_down(miniquad::KeyCode::Tab) {
-            if game.selected_id < (game.block_textures.len() - 1) as u32 {
-                game.selected_id += 1;
-            } else {
-                game.selected_id = 1;
-            }
+            game.cycle_selected(1);
         }
         game.block_material.set_uniform("camera_zoom", camera.zoom);
 
@@ -375,6 +707,8 @@ async fn main() {
         let camera_screen_world =
             camera.screen_to_world(vec2(mouse_position().0, mouse_position().1));
         let csw_in_isometric = iso_to_world(camera_screen_world, TILE_SIZE);
+        // the block under the mouse and the face of it that's pointed at
+        let picked = game.world.pick(csw_in_isometric);
         let grid_pos = Vec2 {
             x: csw_in_isometric.x - 4.,
             y: csw_in_isometric.y - 4.,
@@ -414,11 +748,9 @@ async fn main() {
         // update physics
         let vel = game.player_object.as_ref().borrow().vel();
         let pos = game.player_object.as_ref().borrow().pos();
-        if game
-            .world
-            .get_block_f((pos + vel * get_frame_time()).with_z(pos.z).floor() + vec3(1.0, 1.0, 0.0))
-            != 0
-        {
+        if game.world.registry().is_solid(game.world.get_block_f(
+            (pos + vel * get_frame_time()).with_z(pos.z).floor() + vec3(1.0, 1.0, 0.0),
+        )) {
             let z = game.player_object.as_ref().borrow().vel().z;
             game.player_object.borrow_mut().set_vel(vec3(0., 0., z));
         }
@@ -447,26 +779,9 @@ async fn main() {
             );
         }
         // selection block
-        {
-            let tile_under_mouse = csw_in_isometric.floor();
-            let mut t = (
-                tile_under_mouse.x as usize + 1,
-                tile_under_mouse.y as usize + 2,
-                player_pos.z as usize,
-            );
-            let mut offset = 0;
-            while game.world.get_block(t.0, t.1, t.2) != 0 {
-                t.2 = t.2 + 1;
-                offset += 1;
-            }
-            if player_pos.distance(vec3(t.0 as f32, t.1 as f32, t.2 as f32)) > 1.0 {
-                draw_tile(
-                    curser_pos_iso.x + 1. - offset as f32,
-                    curser_pos_iso.y + 1. - offset as f32,
-                    TILE_SIZE,
-                    &game.selection_top,
-                );
-            }
+        if let Some(hit) = picked {
+            let p = flatten_iso(hit.pos.as_vec3());
+            draw_tile(p.x, p.y, TILE_SIZE, &game.selection_top);
         }
         push_camera_state();
         set_default_camera();
@@ -491,26 +806,75 @@ async fn main() {
         }
         let tile_under_mouse = csw_in_isometric.floor();
         // place block on the mouse click
-        if is_mouse_button_pressed(MouseButton::Left)
-            || (is_mouse_button_down(MouseButton::Left) && is_key_down(miniquad::KeyCode::LeftControl))
-            && /*works partialy*/ !root_ui().is_mouse_over(vec2(mouse_position().0,mouse_position().1))
+        // everything changed while a button is held is undone at once
+        if is_mouse_button_pressed(MouseButton::Left) || is_mouse_button_pressed(MouseButton::Right)
         {
-            let mut t = (
-                tile_under_mouse.x as usize + 1,
-                tile_under_mouse.y as usize + 2,
-                player_pos.z as usize,
-            );
-            while game.world.get_block(t.0, t.1, t.2) != 0 {
-                t.2 = t.2 + 1;
+            game.history.begin_stroke();
+        }
+        if is_mouse_button_released(MouseButton::Left)
+            || is_mouse_button_released(MouseButton::Right)
+        {
+            game.history.end_stroke();
+        }
+        // holding ctrl keeps breaking or placing while the button is down
+        let clicked = |button| {
+            (is_mouse_button_pressed(button)
+                || (is_mouse_button_down(button) && is_key_down(miniquad::KeyCode::LeftControl)))
+                && !root_ui().is_mouse_over(mouse_position().into())
+        };
+        // a block is broken by holding the button on it, after that only ctrl keeps breaking
+        let digging = is_mouse_button_down(MouseButton::Left)
+            && (clicked(MouseButton::Left) || game.breaking.is_some());
+        if !digging || game.tool_mode {
+            game.breaking = None;
+        }
+        let eye = game.player().eye();
+        // only what the player can reach can be changed
+        let reachable = picked.filter(|hit| eye.distance(hit.pos.as_vec3() + 0.5) <= REACH);
+        if game.tool_mode {
+            if let Some(hit) = picked {
+                use_tools(&mut game, hit.pos);
+            }
+        } else if let Some(hit) = reachable.filter(|_| digging) {
+            // harder blocks take longer to break, the time starts over on another block
+            let time = match game.breaking {
+                Some((pos, time)) if pos == hit.pos => time + get_frame_time(),
+                _ => get_frame_time(),
+            };
+            let hardness = game
+                .world
+                .registry()
+                .get(game.world.get_block(hit.pos))
+                .hardness;
+            if time < hardness {
+                game.breaking = Some((hit.pos, time));
+            } else {
+                game.breaking = None;
+                if let Err(e) = game
+                    .history
+                    .edit(&mut game.world, |w| w.set_block(hit.pos, AIR))
+                {
+                    game.notify(format!("Can't break block: {e}"));
+                }
             }
-            if player_pos.distance(vec3(t.0 as f32, t.1 as f32, t.2 as f32)) > 1.0 {
-                game.world.set_block(t.0, t.1, t.2, game.selected_id as u8);
-                // reload the draw queue to get all the new blocks
-                game.draw_queue.clear();
-                game.draw_queue.push(game.player_object.clone());
-                for ele in game.world.blocks() {
-                    game.draw_queue
-                        .push(Rc::new(RefCell::new(objects::Block::new(ele.0, ele.1))));
+        } else if let Some(hit) = reachable.filter(|_| clicked(MouseButton::Right)) {
+            // blocks go against the face pointed at
+            let t = hit.pos + hit.face.normal();
+            let solid = game.world.registry().is_solid(game.selected_id);
+            if solid && game.player().intersects_block(t) {
+                game.notify("Can't place a block inside the player".to_string());
+            } else {
+                let mut state = BlockState::new(game.selected_id);
+                if game.world.registry().get(game.selected_id).rotatable {
+                    // rotatable blocks face the player that placed them
+                    state = state
+                        .with_facing(Facing::from_direction(player_pos.xy() - t.as_vec3().xy()));
+                }
+                if let Err(e) = game
+                    .history
+                    .edit(&mut game.world, |w| w.set_state(t, state))
+                {
+                    game.notify(format!("Can't place block: {e}"));
                 }
             }
         }
@@ -550,7 +914,37 @@ async fn main() {
                 None,
                 format!("Player: {}", game.player_object.as_ref().borrow().pos()).as_str(),
             );
-            ui.button(None, format!("Cursor: {tile_under_mouse}").as_str());
+            match picked {
+                Some(hit) => {
+                    ui.button(None, format!("Cursor: {} {:?}", hit.pos, hit.face).as_str())
+                }
+                None => ui.button(None, "Cursor: -"),
+            };
+            if let Some((pos, time)) = game.breaking {
+                let hardness = game
+                    .world
+                    .registry()
+                    .get(game.world.get_block(pos))
+                    .hardness;
+                ui.button(
+                    None,
+                    format!("Breaking: {:.0}%", time / hardness * 100.).as_str(),
+                );
+            }
+            // blocks owning data show it while they're pointed at
+            if let Some(entity) = picked.and_then(|hit| game.world.block_entity(hit.pos)) {
+                ui.button(None, entity.describe().as_str());
+            }
+            ui.button(
+                None,
+                format!(
+                    "Biome: {}",
+                    game.world
+                        .biome_at(player_pos.x.floor() as i32, player_pos.y.floor() as i32)
+                        .name()
+                )
+                .as_str(),
+            );
             ui.button(None, format!("FPS: {}", get_fps()).as_str());
             ui.button(
                 None,
@@ -571,51 +965,130 @@ async fn main() {
             }
             ui.button(
                 None,
-                format!("Current BlockID: {}", game.selected_id).as_str(),
+                format!(
+                    "Current Block: {} ({})",
+                    game.world.registry().get(game.selected_id).name,
+                    game.selected_id
+                )
+                .as_str(),
             );
             ui.texture(
                 game.block_textures[game.selected_id as usize].clone(),
                 32.0,
                 32.0,
             );
+            if game.tool_mode {
+                let corner = |c: Option<IVec3>| c.map_or("-".to_string(), |c| c.to_string());
+                ui.label(
+                    None,
+                    &format!(
+                        "Selection: {} {}",
+                        corner(game.selection[0]),
+                        corner(game.selection[1])
+                    ),
+                );
+            }
+            if let Some((message, until)) = &game.message {
+                if get_time() < *until {
+                    ui.label(None, message);
+                }
+            }
         });
-        if is_mouse_button_pressed(MouseButton::Right) {
+        if is_key_pressed(miniquad::KeyCode::B) {
             game.ui_selection_mode = !game.ui_selection_mode;
             if game.ui_selection_mode {
                 game.ui_selection_pos = mouse_position().into();
-            } else {
             }
         }
         if game.ui_selection_mode {
             root_ui().button(game.ui_selection_pos, "Select Block:");
-            for (id, t) in game.block_textures.iter().enumerate() {
-                if id == 0 {
-                    continue;
-                }
+            for (i, id) in game.world.registry().placeable().enumerate() {
                 let s = 32.;
                 root_ui().canvas().image(
                     Rect::new(
                         game.ui_selection_pos.x,
-                        game.ui_selection_pos.y + (s + 2.0) * id as f32,
+                        game.ui_selection_pos.y + (s + 2.0) * (i + 1) as f32,
                         s,
                         s,
                     ),
-                    t,
+                    &game.block_textures[id as usize],
                 );
             }
         }
+        // structures: G picks the next one, R turns it and P places it under the cursor
+        if is_key_pressed(miniquad::KeyCode::G) {
+            game.selected_structure = (game.selected_structure + 1) % game.structures.len();
+            let name = game.structures[game.selected_structure].name;
+            game.notify(format!("Structure: {name}"));
+        }
+        if is_key_pressed(miniquad::KeyCode::R) {
+            game.structure_rotation = game.structure_rotation.next();
+            game.notify(format!("Structure rotation: {:?}", game.structure_rotation));
+        }
+        if let Some(hit) = picked.filter(|_| is_key_pressed(miniquad::KeyCode::P)) {
+            // structures stand on the block under the cursor
+            let t = hit.pos + IVec3::Z;
+            let structure = &game.structures[game.selected_structure];
+            let rotation = game.structure_rotation;
+            let result = game
+                .history
+                .edit(&mut game.world, |w| structure.place(w, t, rotation));
+            if let Err(e) = result {
+                game.notify(format!("Structure didn't fit: {e}"));
+            }
+        }
+        if is_key_pressed(miniquad::KeyCode::T) {
+            game.tool_mode = !game.tool_mode;
+            let mode = if game.tool_mode { "on" } else { "off" };
+            game.notify(format!("Tool mode {mode}"));
+        }
+        if is_key_down(miniquad::KeyCode::LeftControl) {
+            if is_key_pressed(miniquad::KeyCode::Z) {
+                match game.history.undo(&mut game.world) {
+                    Ok(true) => {}
+                    Ok(false) => game.notify("Nothing to undo".to_string()),
+                    Err(e) => game.notify(format!("Can't undo: {e}")),
+                }
+            }
+            if is_key_pressed(miniquad::KeyCode::Y) {
+                match game.history.redo(&mut game.world) {
+                    Ok(true) => {}
+                    Ok(false) => game.notify("Nothing to redo".to_string()),
+                    Err(e) => game.notify(format!("Can't redo: {e}")),
+                }
+            }
+        }
+        if is_key_pressed(miniquad::KeyCode::F5) {
+            match save_game(&game) {
+                Ok(()) => game.notify(format!("Saved world to {SAVE_PATH}")),
+                Err(e) => game.notify(format!("Couldn't save: {e}")),
+            }
+        }
+        if is_key_pressed(miniquad::KeyCode::F9) {
+            match load_game(&mut game) {
+                Ok(()) => {
+                    game.history.clear();
+                    game.reload_draw_queue();
+                    game.notify(format!("Loaded world from {SAVE_PATH}"));
+                }
+                Err(e) => game.notify(format!("Couldn't load: {e}")),
+            }
+        }
+        // number keys pick the n-th placeable block
         let z = match get_last_key_pressed() {
-            Some(macroquad::input::KeyCode::Key1) => Some(1),
-            Some(macroquad::input::KeyCode::Key2) => Some(2),
-            Some(macroquad::input::KeyCode::Key3) => Some(3),
-            Some(macroquad::input::KeyCode::Key4) => Some(4),
-            Some(macroquad::input::KeyCode::Key5) => Some(5),
-            Some(macroquad::input::KeyCode::Key6) => Some(6),
-            Some(macroquad::input::KeyCode::Key7) => Some(7),
+            Some(macroquad::input::KeyCode::Key1) => Some(0),
+            Some(macroquad::input::KeyCode::Key2) => Some(1),
+            Some(macroquad::input::KeyCode::Key3) => Some(2),
+            Some(macroquad::input::KeyCode::Key4) => Some(3),
+            Some(macroquad::input::KeyCode::Key5) => Some(4),
+            Some(macroquad::input::KeyCode::Key6) => Some(5),
+            Some(macroquad::input::KeyCode::Key7) => Some(6),
+            Some(macroquad::input::KeyCode::Key8) => Some(7),
+            Some(macroquad::input::KeyCode::Key9) => Some(8),
             _ => None,
         };
-        if z.is_some() {
-            game.selected_id = z.unwrap();
+        if let Some(id) = z.and_then(|n| game.world.registry().placeable().nth(n)) {
+            game.selected_id = id;
         }
         next_frame().await;
     }
@@ -0,0 +1,152 @@
+/****************************************
+   Seeded Noise
+   deterministic hashing and gradient noise
+   used by world generation, the same seed
+   always gives the same values on every platform
+****************************************/
+use std::f32::consts::{FRAC_1_SQRT_2, SQRT_2};
+
+/// mixes a seed and integer coordinates into a well distributed 64 bit value
+#[inline]
+pub fn hash3(seed: u64, x: i32, y: i32, z: i32) -> u64 {
+    let mut h = seed
+        ^ (x as u32 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u32 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (z as u32 as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+    // splitmix64 finalizer
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 31)
+}
+/// a value in `0.0..1.0` picked by the seed and coordinates
+#[inline]
+pub fn rand3(seed: u64, x: i32, y: i32, z: i32) -> f32 {
+    (hash3(seed, x, y, z) >> 40) as f32 / (1u64 << 24) as f32
+}
+/// derives an independent seed for a different use of the same world seed
+#[inline]
+pub fn sub_seed(seed: u64, salt: u64) -> u64 {
+    hash3(seed ^ salt.wrapping_mul(0xD6E8_FEB8_6659_FD93), 0, 0, 0)
+}
+
+#[inline]
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+#[inline]
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// gradient noise with a fixed seed
+#[derive(Debug, Clone, Copy)]
+pub struct Noise {
+    seed: u64,
+}
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+    fn grad2(&self, ix: i32, iy: i32, dx: f32, dy: f32) -> f32 {
+        const D: f32 = FRAC_1_SQRT_2;
+        const G: [(f32, f32); 8] = [
+            (1., 0.),
+            (-1., 0.),
+            (0., 1.),
+            (0., -1.),
+            (D, D),
+            (-D, D),
+            (D, -D),
+            (-D, -D),
+        ];
+        let (gx, gy) = G[(hash3(self.seed, ix, iy, 0) & 7) as usize];
+        gx * dx + gy * dy
+    }
+    fn grad3(&self, ix: i32, iy: i32, iz: i32, dx: f32, dy: f32, dz: f32) -> f32 {
+        // the 12 edges of a cube
+        match hash3(self.seed, ix, iy, iz) % 12 {
+            0 => dx + dy,
+            1 => -dx + dy,
+            2 => dx - dy,
+            3 => -dx - dy,
+            4 => dx + dz,
+            5 => -dx + dz,
+            6 => dx - dz,
+            7 => -dx - dz,
+            8 => dy + dz,
+            9 => -dy + dz,
+            10 => dy - dz,
+            _ => -dy - dz,
+        }
+    }
+    /// 2d gradient noise, roughly in `-1.0..1.0`
+    pub fn get2(&self, x: f32, y: f32) -> f32 {
+        let (ix, iy) = (x.floor() as i32, y.floor() as i32);
+        let (fx, fy) = (x - ix as f32, y - iy as f32);
+        let (u, v) = (fade(fx), fade(fy));
+        let a = lerp(
+            self.grad2(ix, iy, fx, fy),
+            self.grad2(ix + 1, iy, fx - 1., fy),
+            u,
+        );
+        let b = lerp(
+            self.grad2(ix, iy + 1, fx, fy - 1.),
+            self.grad2(ix + 1, iy + 1, fx - 1., fy - 1.),
+            u,
+        );
+        lerp(a, b, v) * SQRT_2
+    }
+    /// 3d gradient noise, roughly in `-1.0..1.0`
+    pub fn get3(&self, x: f32, y: f32, z: f32) -> f32 {
+        let (ix, iy, iz) = (x.floor() as i32, y.floor() as i32, z.floor() as i32);
+        let (fx, fy, fz) = (x - ix as f32, y - iy as f32, z - iz as f32);
+        let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+        let g = |ox: i32, oy: i32, oz: i32| {
+            self.grad3(
+                ix + ox,
+                iy + oy,
+                iz + oz,
+                fx - ox as f32,
+                fy - oy as f32,
+                fz - oz as f32,
+            )
+        };
+        let x00 = lerp(g(0, 0, 0), g(1, 0, 0), u);
+        let x10 = lerp(g(0, 1, 0), g(1, 1, 0), u);
+        let x01 = lerp(g(0, 0, 1), g(1, 0, 1), u);
+        let x11 = lerp(g(0, 1, 1), g(1, 1, 1), u);
+        lerp(lerp(x00, x10, v), lerp(x01, x11, v), w)
+    }
+    /// several octaves of 2d noise added together, each twice as detailed and half as strong
+    pub fn fbm2(&self, x: f32, y: f32, octaves: u32) -> f32 {
+        let (mut sum, mut amp, mut freq, mut norm) = (0., 1., 1., 0.);
+        for o in 0..octaves {
+            // offset every octave so their lattices don't line up
+            let off = o as f32 * 17.31;
+            sum += Noise::new(self.seed.wrapping_add(o as u64))
+                .get2(x * freq + off, y * freq + off)
+                * amp;
+            norm += amp;
+            amp *= 0.5;
+            freq *= 2.;
+        }
+        sum / norm
+    }
+}
+#[test]
+fn noise_is_deterministic_test() {
+    let a = Noise::new(42);
+    let b = Noise::new(42);
+    let c = Noise::new(43);
+    let mut differs = false;
+    for i in 0..100 {
+        let (x, y) = (i as f32 * 0.37, i as f32 * -0.91);
+        assert_eq!(a.get2(x, y), b.get2(x, y));
+        assert!((-1.0..=1.0).contains(&a.fbm2(x, y, 4)));
+        differs |= a.get2(x, y) != c.get2(x, y);
+    }
+    assert!(differs);
+    // noise is zero on lattice points
+    assert_eq!(a.get2(3., -7.), 0.);
+    assert_eq!(a.get3(3., -7., 2.), 0.);
+}
@@ -1,23 +1,45 @@
 use macroquad::{
-    color::WHITE,
-    math::{vec3, Rect, Vec3},
-    prelude::{gl_use_default_material, gl_use_material},
+    color::Color,
+    math::{vec2, vec3, IVec3, Rect, Vec2, Vec3, Vec4},
+    prelude::{
+        draw_texture_ex, gl_use_default_material, gl_use_material, DrawTextureParams, WHITE,
+    },
 };
 
 use crate::{
-    constants, draw_tile, draw_tile_ex, flatten_iso, DrawTilesParams, Game, PlayerOrient, TILE_SIZE,
+    constants, draw_tile, draw_tile_ex,
+    entity::EntityKind,
+    flatten_iso,
+    fluid::fluid_height,
+    light::brightness,
+    picking::Face,
+    power::{Power, MAX_SIGNAL},
+    state::{BlockState, Facing},
+    transform_tile,
+    world::World,
+    DrawTilesParams, Game, PlayerOrient, TILE_SIZE,
 };
 
 #[derive(Debug)]
 pub struct Player {
     pos: Vec3,
     vel: Vec3,
+    #[allow(dead_code)]
     pub is_jumping: bool,
     pub orient: PlayerOrient,
 }
 
 pub struct Block {
-    pub block_id: u8,
+    pub state: BlockState,
+    pos: Vec3,
+    /// how bright the block is drawn and how dark the corners of it's top, left and right
+    /// faces are, worked out when it or the blocks around it change and not every frame
+    light: f32,
+    occlusion: [Vec4; 3],
+}
+/// how an entity of the world is drawn, these are made fresh every frame
+pub struct EntitySprite {
+    pub kind: EntityKind,
     pos: Vec3,
 }
 pub trait Positionable {
@@ -27,6 +49,7 @@ pub trait Positionable {
 pub trait Physical: Positionable {
     fn vel(&self) -> Vec3;
     fn set_vel(&mut self, vel: Vec3);
+    /// footprint on the xy plane, `None` for things nothing collides with
     fn collision_box(&self) -> Option<Rect>;
 }
 pub trait Renderble: Positionable {
@@ -34,6 +57,9 @@ pub trait Renderble: Positionable {
 }
 
 impl Player {
+    /// half the width of the player's body
+    const HALF_WIDTH: f32 = 0.3;
+    const HEIGHT: f32 = 1.0;
     pub fn new(pos: Vec3, vel: Vec3) -> Self {
         Player {
             pos,
@@ -42,6 +68,23 @@ impl Player {
             orient: PlayerOrient::_45,
         }
     }
+    /// where the player looks from, reach is measured from here
+    pub fn eye(&self) -> Vec3 {
+        self.pos + vec3(1., 1., Self::HEIGHT * 0.8)
+    }
+    /// whether the player's body overlaps the block at a position
+    pub fn intersects_block(&self, pos: IVec3) -> bool {
+        let Some(r) = self.collision_box() else {
+            return false;
+        };
+        let p = pos.as_vec3();
+        r.x < p.x + 1.
+            && r.x + r.w > p.x
+            && r.y < p.y + 1.
+            && r.y + r.h > p.y
+            && self.pos.z < p.z + 1.
+            && self.pos.z + Self::HEIGHT > p.z
+    }
     pub fn update_orientation(&mut self, degrees: f32) {
         let q: f32 = 45. / 2.;
         let ors = &[
@@ -78,9 +121,37 @@ impl Positionable for Player {
 }
 
 impl Block {
-    pub fn new(pos: Vec3, block_id: u8) -> Block {
-        Block { block_id, pos }
+    pub fn new(pos: Vec3, state: BlockState, world: &World) -> Block {
+        let mut block = Block {
+            state,
+            pos,
+            light: 1.,
+            occlusion: [Vec4::ONE; 3],
+        };
+        block.update(state, world);
+        block
+    }
+    /// takes on a new state and works out the light and occlusion again
+    pub fn update(&mut self, state: BlockState, world: &World) {
+        self.state = state;
+        // as bright as the light falling on the visible faces
+        let cell = self.pos.as_ivec3();
+        let def = world.registry().get(state.id);
+        let level = [IVec3::X, IVec3::Y, IVec3::Z]
+            .iter()
+            .map(|d| world.light_at(cell + *d))
+            .max()
+            .unwrap_or(0)
+            .max(def.light_emission);
+        self.light = brightness(level);
+        // wires glow with the signal they carry
+        if def.power == Some(Power::Conductor) {
+            self.light *= 0.5 + 0.5 * state.signal() as f32 / MAX_SIGNAL as f32;
+        }
+        self.occlusion =
+            [Face::Top, Face::Left, Face::Right].map(|f| world.face_occlusion(cell, f));
     }
+    #[allow(dead_code)]
     pub fn bbox(&self, pos: Vec3) -> bool {
         /*
         bool PointIsInCube( Point3D p, float x_max, float x_min, float y_max, float y_min, float z_max, float z_min)
@@ -103,13 +174,35 @@ impl Positionable for Block {
         self.pos = pos
     }
 }
+impl EntitySprite {
+    pub fn new(pos: Vec3, kind: EntityKind) -> Self {
+        Self { kind, pos }
+    }
+}
+impl Positionable for EntitySprite {
+    fn pos(&self) -> Vec3 {
+        self.pos
+    }
+
+    fn set_pos(&mut self, pos: Vec3) {
+        self.pos = pos
+    }
+}
 /*
    Graphics
 */
+#[allow(dead_code)]
 const RENDER_DISTANCE: f32 = 8.;
 impl Renderble for Block {
     fn render(&self, game_state: &Game) {
-        let c = WHITE;
+        // blocks take on the tint of the biome they're in
+        let tint = game_state
+            .world
+            .biome_at(self.pos.x.floor() as i32, self.pos.y.floor() as i32)
+            .tint();
+        // and the light falling on them
+        let light = self.light;
+        let c = Color::new(tint.r * light, tint.g * light, tint.b * light, tint.a);
         let player_pos = game_state.player().pos();
         let player_pos_i = flatten_iso(player_pos);
         let p = flatten_iso(self.pos);
@@ -132,32 +225,68 @@ impl Renderble for Block {
         game_state
             .block_material
             .set_uniform("block_world_pos", self.pos);
+        // fluids and growing crops only fill part of their block so their tile sinks into it
+        let def = game_state.world.registry().get(self.state.id);
+        let cut = if def.fluid.is_some() {
+            1. - fluid_height(self.state)
+        } else if def.crop {
+            1. - (self.state.stage() + 1) as f32 / 8.
+        } else {
+            0.
+        };
+        let p = p + cut;
+        game_state.block_material.set_uniform("tile_cut", cut);
+        // corners and crevices of the visible faces darken with the blocks crowding them
+        let (ox, oy) = transform_tile(p.x - 1., p.y - 1., TILE_SIZE);
+        let material = &game_state.block_material;
+        material.set_uniform("tile_origin", vec2(ox, oy));
+        material.set_uniform("tile_size", Vec2::from(TILE_SIZE));
+        for (name, occlusion) in ["ao_top", "ao_left", "ao_right"].iter().zip(self.occlusion) {
+            material.set_uniform(name, occlusion);
+        }
         gl_use_material(&game_state.block_material);
         draw_tile_ex(
             p.x,
             p.y,
             TILE_SIZE,
-            &game_state.block_textures[self.block_id as usize],
+            &game_state.block_textures[self.state.id as usize],
             DrawTilesParams {
                 color: c,
+                // we only have one tile per block so facing along y is drawn mirrored
+                flip_x: game_state.world.registry().get(self.state.id).rotatable
+                    && matches!(self.state.facing(), Facing::South | Facing::North),
                 ..Default::default()
             },
         );
-        // draw the shade if it's right under a block and is visible (like when player is seeing trough)
-        if top != 0 && (dist_to_player < 5.0 && !game_state.block_trans_map.contains(&(top as u32)))
-        {
-            draw_tile_ex(
+        gl_use_default_material();
+    }
+}
+
+impl Renderble for EntitySprite {
+    fn render(&self, game_state: &Game) {
+        let p = flatten_iso(self.pos);
+        match self.kind {
+            EntityKind::FallingBlock(state) => draw_tile(
                 p.x,
                 p.y,
                 TILE_SIZE,
-                &game_state.shade_top,
-                DrawTilesParams {
-                    color: c,
-                    ..Default::default()
-                },
-            );
+                &game_state.block_textures[state.id as usize],
+            ),
+            // items are a small block sitting in the middle of their cell
+            EntityKind::Item(state) => {
+                let (x, y) = transform_tile(p.x - 1., p.y - 1., TILE_SIZE);
+                draw_texture_ex(
+                    &game_state.block_textures[state.id as usize],
+                    x + TILE_SIZE.0 / 4.,
+                    y + TILE_SIZE.1 / 2.,
+                    WHITE,
+                    DrawTextureParams {
+                        dest_size: Some(Vec2::from(TILE_SIZE) / 2.),
+                        ..Default::default()
+                    },
+                );
+            }
         }
-        gl_use_default_material();
     }
 }
 
@@ -181,6 +310,25 @@ impl Physical for Player {
     }
 
     fn collision_box(&self) -> Option<Rect> {
-        todo!()
+        // the player stands on the cell diagonal to it's position
+        let w = Player::HALF_WIDTH;
+        Some(Rect::new(
+            self.pos.x + 1. - w,
+            self.pos.y + 1. - w,
+            w * 2.,
+            w * 2.,
+        ))
     }
 }
+#[test]
+fn player_collision_test() {
+    use macroquad::math::ivec3;
+    let player = Player::new(vec3(2.5, 2.5, 4.), Vec3::ZERO);
+    assert!(player.intersects_block(ivec3(3, 3, 4)));
+    assert!(!player.intersects_block(ivec3(3, 3, 5)));
+    assert!(!player.intersects_block(ivec3(3, 3, 3)));
+    assert!(!player.intersects_block(ivec3(4, 3, 4)));
+    // the body is wider than a point, standing near an edge touches the next cell
+    let player = Player::new(vec3(2.9, 2.5, 4.), Vec3::ZERO);
+    assert!(player.intersects_block(ivec3(4, 3, 4)));
+}
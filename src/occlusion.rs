@@ -0,0 +1,97 @@
+/****************************************
+   Ambient Occlusion
+   darkens the corners of the visible faces of a
+   block by how many blocks crowd around them,
+   the tile shader blends the corners over a face
+****************************************/
+use macroquad::math::{ivec3, IVec3, Vec4};
+
+use crate::{picking::Face, world::World};
+
+/// brightness of a corner by how open it is (0 = boxed in, 3 = nothing around)
+const CORNER_BRIGHTNESS: [f32; 4] = [0.55, 0.7, 0.85, 1.0];
+
+impl Face {
+    /// the directions along the face towards it's corners, corners are taken in the order
+    /// `(-a, -b), (+a, -b), (-a, +b), (+a, +b)` which is how the shader lays them out
+    fn tangents(self) -> (IVec3, IVec3) {
+        match self {
+            Face::Top => (IVec3::X, IVec3::Y),
+            // sides go from their top edge down
+            Face::Left => (IVec3::X, IVec3::NEG_Z),
+            Face::Right => (IVec3::Y, IVec3::NEG_Z),
+        }
+    }
+}
+
+/// positions of the blocks whose faces a block at `pos` can darken, it's in front of one of
+/// their visible faces or next to it
+pub fn shaded_by(pos: IVec3) -> impl Iterator<Item = IVec3> {
+    (-1..=1)
+        .flat_map(|z| (-1..=1).flat_map(move |y| (-1..=1).map(move |x| ivec3(x, y, z))))
+        .filter(|d| d.max_element() == 1)
+        .map(move |d| pos - d)
+}
+
+impl World {
+    fn occludes(&self, pos: IVec3) -> bool {
+        !self.registry().is_transparent(self.get_block(pos))
+    }
+    /// brightness of the 4 corners of a face of the block at `pos`
+    pub fn face_occlusion(&self, pos: IVec3, face: Face) -> Vec4 {
+        let (a, b) = face.tangents();
+        let front = pos + face.normal();
+        let corner = |sa: i32, sb: i32| {
+            let side_a = self.occludes(front + a * sa);
+            let side_b = self.occludes(front + b * sb);
+            let diagonal = self.occludes(front + a * sa + b * sb);
+            // two sides hide the corner no matter what's in between
+            let open = if side_a && side_b {
+                0
+            } else {
+                3 - (side_a as usize + side_b as usize + diagonal as usize)
+            };
+            CORNER_BRIGHTNESS[open]
+        };
+        Vec4::new(corner(-1, -1), corner(1, -1), corner(-1, 1), corner(1, 1))
+    }
+}
+#[test]
+fn occlusion_test() {
+    use crate::registry::STONE;
+    let mut world = World::new();
+    let b = ivec3(0, 0, 0);
+    world.set_block(b, STONE).unwrap();
+    assert_eq!(world.face_occlusion(b, Face::Top), Vec4::ONE);
+    // a wall along +x darkens the +x corners of the top
+    world.set_block(ivec3(1, 0, 1), STONE).unwrap();
+    let top = world.face_occlusion(b, Face::Top);
+    assert!(top.y < 1. && top.w < 1. && top.x == 1. && top.z == 1.);
+    // it's above the block in front of the right face too, darkening that face's top corners
+    let right = world.face_occlusion(b, Face::Right);
+    assert!(right.x < 1. && right.y < 1. && right.z == 1. && right.w == 1.);
+    // a corner boxed in from both sides is as dark as it gets
+    world.set_block(ivec3(0, 1, 1), STONE).unwrap();
+    assert_eq!(world.face_occlusion(b, Face::Top).w, CORNER_BRIGHTNESS[0]);
+    // blocks only darken the faces of the blocks they say they shade
+    let mut world = World::new();
+    let b = ivec3(0, 0, 3);
+    world.set_block(b, STONE).unwrap();
+    let faces = |w: &World| [Face::Top, Face::Left, Face::Right].map(|f| w.face_occlusion(b, f));
+    let open = faces(&world);
+    for z in -2..=2 {
+        for y in -2..=2 {
+            for x in -2..=2 {
+                let p = b + ivec3(x, y, z);
+                if p == b {
+                    continue;
+                }
+                world.set_block(p, STONE).unwrap();
+                if faces(&world) != open {
+                    assert!(shaded_by(p).any(|s| s == b), "{p} shades {b}");
+                }
+                world.set_block(p, 0).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+/****************************************
+   Picking
+   finds the block under a point of the screen
+   by walking the voxel grid along the view ray,
+   the camera looks down the (-1, -1, -1) diagonal
+   so only the top and the +x/+y faces show
+****************************************/
+#[cfg(test)]
+use macroquad::math::ivec3;
+use macroquad::math::{vec3, IVec3, Vec2};
+
+use crate::{chunk::HEIGHT, registry::AIR, world::World};
+
+/// the faces of a block that can be seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Face {
+    /// facing +z
+    Top,
+    /// facing +y, on the left of a tile
+    Left,
+    /// facing +x, on the right of a tile
+    Right,
+}
+impl Face {
+    /// direction the face points to
+    pub fn normal(self) -> IVec3 {
+        match self {
+            Face::Top => IVec3::Z,
+            Face::Left => IVec3::Y,
+            Face::Right => IVec3::X,
+        }
+    }
+}
+
+/// a block found under the cursor and the face the view ray went in through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PickHit {
+    pub pos: IVec3,
+    pub face: Face,
+}
+
+/// every cell the view ray through an isometric grid position goes through,
+/// from the top of the world down, with the face it enters them through
+///
+/// `cursor` is in the same grid as [`crate::math::flatten_iso`], tiles are drawn
+/// half a block off the grid so the ray is moved to line up with the cells as they look
+pub fn view_ray(cursor: Vec2) -> impl Iterator<Item = (IVec3, Face)> {
+    let start = vec3(
+        cursor.x - 0.5 + HEIGHT as f32,
+        cursor.y + 0.5 + HEIGHT as f32,
+        HEIGHT as f32,
+    );
+    let mut cell = start.floor().as_ivec3();
+    // how far along the ray the next cell boundary of every axis is, the ray moves
+    // the same amount on every axis so stepping one cell always costs 1
+    let mut next = start - start.floor();
+    std::iter::from_fn(move || {
+        let face = if next.z <= next.x && next.z <= next.y {
+            cell.z -= 1;
+            next.z += 1.;
+            Face::Top
+        } else if next.x <= next.y {
+            cell.x -= 1;
+            next.x += 1.;
+            Face::Right
+        } else {
+            cell.y -= 1;
+            next.y += 1.;
+            Face::Left
+        };
+        (cell.z >= 0).then_some((cell, face))
+    })
+}
+
+impl World {
+    /// the first block that isn't air or a fluid along the view ray through an isometric
+    /// grid position, blocks that can be walked through like wheat can be pointed at too,
+    /// fluids are looked through so what's under water can be reached
+    pub fn pick(&self, cursor: Vec2) -> Option<PickHit> {
+        view_ray(cursor)
+            .find(|(pos, _)| {
+                let id = self.get_block(*pos);
+                id != AIR && !self.registry().is_fluid(id)
+            })
+            .map(|(pos, face)| PickHit { pos, face })
+    }
+}
+#[test]
+fn pick_test() {
+    use crate::registry::{STONE, WATER, WHEAT};
+    use macroquad::math::vec2;
+    let mut world = World::new();
+    let b = ivec3(4, 7, 10);
+    world.set_block(b, STONE).unwrap();
+    // the middle of the top and the right face as they're drawn
+    let top = vec2((b.x - b.z) as f32, (b.y - b.z) as f32 - 1.);
+    let right = vec2((b.x - b.z) as f32 + 1., (b.y - b.z) as f32 - 0.5);
+    let left = vec2((b.x - b.z) as f32 + 0.5, (b.y - b.z) as f32);
+    assert_eq!(
+        world.pick(top),
+        Some(PickHit {
+            pos: b,
+            face: Face::Top
+        })
+    );
+    assert_eq!(
+        world.pick(right),
+        Some(PickHit {
+            pos: b,
+            face: Face::Right
+        })
+    );
+    assert_eq!(
+        world.pick(left),
+        Some(PickHit {
+            pos: b,
+            face: Face::Left
+        })
+    );
+    // blocks further down never cover a top face, blocks in front and above do
+    world.set_block(b + ivec3(1, 1, -1), STONE).unwrap();
+    assert_eq!(world.pick(top).map(|h| h.pos), Some(b));
+    world.set_block(b + ivec3(1, 1, 1), STONE).unwrap();
+    assert_eq!(world.pick(top).map(|h| h.pos), Some(b + ivec3(1, 1, 1)));
+    // a block next to a face covers it
+    world.set_block(b + ivec3(1, 1, 1), 0).unwrap();
+    world.set_block(b + IVec3::X, STONE).unwrap();
+    assert_eq!(world.pick(right).map(|h| h.pos), Some(b + IVec3::X));
+    assert_eq!(world.pick(left).map(|h| h.pos), Some(b));
+    assert_eq!(world.pick(vec2(100., 100.)), None);
+    // blocks that aren't solid are picked as well
+    world.set_block(b + IVec3::X, WHEAT).unwrap();
+    assert!(!world.registry().is_solid(WHEAT));
+    assert_eq!(world.pick(right).map(|h| h.pos), Some(b + IVec3::X));
+    // but the stone under water is picked instead of the water
+    world.set_block(b + IVec3::X, 0).unwrap();
+    world.set_block(b + IVec3::Z, WATER).unwrap();
+    assert_eq!(
+        world.pick(top),
+        Some(PickHit {
+            pos: b,
+            face: Face::Top
+        })
+    );
+}
@@ -0,0 +1,136 @@
+/****************************************
+   Power
+   wires carry a signal (0-15) from power
+   sources to the machines next to them, it
+   drops a level for every wire it travels,
+   wires keep their signal in their state and
+   a network is worked out again whenever a
+   block in it or next to it changes
+****************************************/
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use macroquad::math::IVec3;
+
+use crate::{
+    state::BlockState,
+    world::{World, NEIGHBORS},
+};
+
+/// the strongest signal there is
+pub const MAX_SIGNAL: u8 = 15;
+
+/// the part a block plays in a power network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Power {
+    /// carries the signal along, keeping it's strength in it's state
+    Conductor,
+    /// powers the blocks next to it with a signal of this strength
+    Source(u8),
+    /// does something while it's powered
+    Consumer,
+}
+
+impl World {
+    fn power_of(&self, pos: IVec3) -> Option<Power> {
+        self.registry().get(self.get_block(pos)).power
+    }
+    /// signal a block gives to the blocks next to it
+    pub fn signal_from(&self, pos: IVec3) -> u8 {
+        let state = self.get_state(pos);
+        match self.registry().get(state.id).power {
+            Some(Power::Source(s)) => s.min(MAX_SIGNAL),
+            Some(Power::Conductor) => state.signal(),
+            _ => 0,
+        }
+    }
+    /// strongest signal reaching a block from the ones next to it
+    pub fn power_at(&self, pos: IVec3) -> u8 {
+        NEIGHBORS
+            .iter()
+            .map(|d| self.signal_from(pos + *d))
+            .max()
+            .unwrap_or(0)
+    }
+    /// works the signal out again for every network a change from `old` to `new` at `pos` touches,
+    /// only the conductors connected to `pos` are looked at
+    pub(crate) fn update_power(&mut self, pos: IVec3, old: BlockState, new: BlockState) {
+        let carries = |s: BlockState| {
+            matches!(
+                self.registry().get(s.id).power,
+                Some(Power::Conductor | Power::Source(_))
+            )
+        };
+        // setting the signals below is a change too
+        if self.powering || !(carries(old) || carries(new)) {
+            return;
+        }
+        // every conductor connected to the changed block, both sides of a cut wire included
+        let mut network = HashSet::new();
+        let mut queue: VecDeque<IVec3> = NEIGHBORS.iter().map(|d| pos + *d).chain([pos]).collect();
+        while let Some(p) = queue.pop_front() {
+            if self.power_of(p) == Some(Power::Conductor) && network.insert(p) {
+                queue.extend(NEIGHBORS.iter().map(|d| p + *d));
+            }
+        }
+        // the signal starts at the wires next to a source and spreads from there
+        let mut signal: HashMap<IVec3, u8> = HashMap::new();
+        for p in &network {
+            let s = NEIGHBORS
+                .iter()
+                .filter_map(|d| match self.power_of(*p + *d) {
+                    Some(Power::Source(s)) => Some(s.min(MAX_SIGNAL)),
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(0);
+            signal.insert(*p, s);
+            if s > 0 {
+                queue.push_back(*p);
+            }
+        }
+        while let Some(p) = queue.pop_front() {
+            let next = signal[&p].saturating_sub(1);
+            for n in NEIGHBORS.iter().map(|d| p + *d) {
+                if let Some(s) = signal.get_mut(&n).filter(|s| **s < next) {
+                    *s = next;
+                    queue.push_back(n);
+                }
+            }
+        }
+        self.powering = true;
+        for (p, s) in signal {
+            let state = self.get_state(p);
+            if state.signal() != s {
+                self.set_state(p, state.with_signal(s)).unwrap();
+            }
+        }
+        self.powering = false;
+    }
+}
+#[test]
+fn power_test() {
+    use crate::registry::{AIR, GENERATOR, MACHINE, WIRE};
+    use macroquad::math::ivec3;
+    let mut world = World::new();
+    world.fill(ivec3(0, 0, 0), ivec3(19, 0, 0), WIRE).unwrap();
+    assert_eq!(world.get_state(ivec3(5, 0, 0)).signal(), 0);
+    // the signal fades along the wire
+    world.set_block(ivec3(-1, 0, 0), GENERATOR).unwrap();
+    assert_eq!(world.get_state(ivec3(0, 0, 0)).signal(), MAX_SIGNAL);
+    assert_eq!(world.get_state(ivec3(5, 0, 0)).signal(), MAX_SIGNAL - 5);
+    assert_eq!(world.get_state(ivec3(15, 0, 0)).signal(), 0);
+    // a machine at the end of the wire is powered once a second source is close enough
+    let machine = ivec3(20, 0, 0);
+    world.set_block(machine, MACHINE).unwrap();
+    assert_eq!(world.power_at(machine), 0);
+    world.set_block(ivec3(18, 1, 0), GENERATOR).unwrap();
+    assert_eq!(world.power_at(machine), MAX_SIGNAL - 1);
+    // cutting the wire leaves the far side to the second source only
+    world.set_block(ivec3(10, 0, 0), AIR).unwrap();
+    assert_eq!(world.get_state(ivec3(9, 0, 0)).signal(), MAX_SIGNAL - 9);
+    assert_eq!(world.get_state(ivec3(11, 0, 0)).signal(), MAX_SIGNAL - 7);
+    // and taking the source away turns the network off
+    world.set_block(ivec3(18, 1, 0), AIR).unwrap();
+    assert_eq!(world.get_state(ivec3(19, 0, 0)).signal(), 0);
+    assert_eq!(world.power_at(machine), 0);
+}
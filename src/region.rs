@@ -0,0 +1,419 @@
+/****************************************
+   Region Files
+   chunks unloaded from the world wait on disk
+   in region files, one for every `REGION_SIZE`
+   by `REGION_SIZE` chunks, named after the
+   region's coordinates (`r.<x>.<y>.isor`):
+
+   | field   | type             | notes                                |
+   |---------|------------------|--------------------------------------|
+   | magic   | `[u8; 4]`        | always `ISOR`                        |
+   | version | `u16`            | [`crate::save::FORMAT_VERSION`]      |
+   | names   | `u16` + entries  | id table like in saves               |
+   | chunks  | `u32` + entries  | see below                            |
+
+   every chunk is stored as it's chunk coordinates (`i32` x, `i32` y), a `u32`
+   length and that many bytes: the chunk like in saves followed by a `u32`
+   count of block entities and the block entities like in saves.
+   the length lets a reader skip the chunks it isn't looking for.
+
+   chunks stored later are appended after the counted ones until the end of
+   the file, the last copy of a chunk is the one that counts. once a file
+   holds too many old copies it's written anew with only the last ones
+****************************************/
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use macroquad::math::{ivec2, IVec2, IVec3};
+
+use crate::{
+    chunk::Chunk,
+    registry::{BlockId, BlockRegistry},
+    save::{
+        read_block_data, read_chunk, read_i32, read_names, read_u16, read_u32, write_block_data,
+        write_chunk, write_names, SaveError, FORMAT_VERSION,
+    },
+};
+
+const MAGIC: &[u8; 4] = b"ISOR";
+/// width and depth of a region in chunks
+pub const REGION_SIZE: i32 = 8;
+/// copies of chunks a region file can hold before it's written anew
+const MAX_ENTRIES: usize = 2 * (REGION_SIZE * REGION_SIZE) as usize;
+
+/// a chunk as it's kept in a region file, with the data of it's block entities
+pub struct StoredChunk {
+    pub pos: IVec2,
+    pub chunk: Chunk,
+    pub block_data: Vec<(IVec3, Vec<u8>)>,
+}
+
+/// coordinates of the region holding a chunk
+pub fn region_of(chunk: IVec2) -> IVec2 {
+    ivec2(
+        chunk.x.div_euclid(REGION_SIZE),
+        chunk.y.div_euclid(REGION_SIZE),
+    )
+}
+
+fn encode_stored(stored: &StoredChunk) -> Result<Vec<u8>, SaveError> {
+    let mut data = Vec::new();
+    write_chunk(&mut data, &stored.chunk)?;
+    data.extend((stored.block_data.len() as u32).to_le_bytes());
+    for (pos, d) in &stored.block_data {
+        write_block_data(&mut data, *pos, d)?;
+    }
+    Ok(data)
+}
+fn decode_stored(
+    pos: IVec2,
+    mut data: &[u8],
+    ids: &[Option<BlockId>; 256],
+    version: u16,
+) -> Result<StoredChunk, SaveError> {
+    let chunk = read_chunk(&mut data, ids, version)?;
+    let block_data = (0..read_u32(&mut data)?)
+        .map(|_| read_block_data(&mut data))
+        .collect::<Result<_, _>>()?;
+    Ok(StoredChunk {
+        pos,
+        chunk,
+        block_data,
+    })
+}
+
+fn write_entry(w: &mut Vec<u8>, pos: IVec2, data: &[u8]) {
+    w.extend(pos.x.to_le_bytes());
+    w.extend(pos.y.to_le_bytes());
+    w.extend((data.len() as u32).to_le_bytes());
+    w.extend(data);
+}
+/// reads the position and bytes of the next chunk of a region file
+fn read_entry<'a>(r: &mut &'a [u8]) -> Result<(IVec2, &'a [u8]), SaveError> {
+    let pos = ivec2(read_i32(r)?, read_i32(r)?);
+    let len = read_u32(r)? as usize;
+    let data = r.get(..len).ok_or(SaveError::Corrupt("chunk cut short"))?;
+    *r = &r[len..];
+    Ok((pos, data))
+}
+
+/// where the chunks of a region file are, kept so a chunk can be read or
+/// written without going through the whole file
+struct RegionIndex {
+    version: u16,
+    /// maps ids of the file to ids of this build
+    ids: [Option<BlockId>; 256],
+    /// where the last copy of every chunk starts in the file and how long it is
+    slots: HashMap<IVec2, (u64, usize)>,
+    /// copies of chunks in the file, old ones included
+    entries: usize,
+    /// where the last whole chunk ends, anything after it was cut short while appending
+    end: u64,
+}
+impl RegionIndex {
+    fn read(data: &[u8], registry: &BlockRegistry) -> Result<Self, SaveError> {
+        let mut r = data;
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SaveError::NotASave);
+        }
+        let version = read_u16(&mut r)?;
+        if !(1..=FORMAT_VERSION).contains(&version) {
+            return Err(SaveError::UnsupportedVersion(version));
+        }
+        let ids = read_names(&mut r, registry)?;
+        let counted = read_u32(&mut r)? as usize;
+        let mut index = Self {
+            version,
+            ids,
+            slots: HashMap::new(),
+            entries: 0,
+            end: (data.len() - r.len()) as u64,
+        };
+        while index.entries < counted || !r.is_empty() {
+            match read_entry(&mut r) {
+                Ok((pos, chunk)) => {
+                    index.end = (data.len() - r.len()) as u64;
+                    index
+                        .slots
+                        .insert(pos, (index.end - chunk.len() as u64, chunk.len()));
+                    index.entries += 1;
+                }
+                // a crash while appending leaves the last chunk cut short, the ones before are fine
+                Err(_) if index.entries >= counted => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(index)
+    }
+    /// whether the file was written with the ids of this build
+    fn same_ids(&self) -> bool {
+        self.version == FORMAT_VERSION
+            && self
+                .ids
+                .iter()
+                .enumerate()
+                .all(|(i, id)| id.is_none_or(|id| id as usize == i))
+    }
+}
+
+/// the region files of a world in a directory
+pub struct RegionStore {
+    dir: PathBuf,
+    /// regions that were read or written since the store was opened
+    index: HashMap<IVec2, RegionIndex>,
+}
+impl RegionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            index: HashMap::new(),
+        }
+    }
+    fn path(&self, region: IVec2) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.isor", region.x, region.y))
+    }
+    /// where the chunks of a region are, `None` if there's no file for it yet,
+    /// the file is only gone through the first time
+    fn index(
+        &mut self,
+        region: IVec2,
+        registry: &BlockRegistry,
+    ) -> Result<Option<&RegionIndex>, SaveError> {
+        if !self.index.contains_key(&region) {
+            let data = match fs::read(self.path(region)) {
+                Ok(data) => data,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            self.index
+                .insert(region, RegionIndex::read(&data, registry)?);
+        }
+        Ok(self.index.get(&region))
+    }
+    /// reads a chunk back, `None` if it was never stored
+    pub fn load(
+        &mut self,
+        pos: IVec2,
+        registry: &BlockRegistry,
+    ) -> Result<Option<StoredChunk>, SaveError> {
+        let region = region_of(pos);
+        let Some(index) = self.index(region, registry)? else {
+            return Ok(None);
+        };
+        let Some(&(start, len)) = index.slots.get(&pos) else {
+            return Ok(None);
+        };
+        let (ids, version) = (index.ids, index.version);
+        let mut file = fs::File::open(self.path(region))?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut data = vec![0; len];
+        file.read_exact(&mut data)?;
+        decode_stored(pos, &data, &ids, version).map(Some)
+    }
+    /// writes chunks to their region files, replacing what was stored for them before,
+    /// every region file touched is written once
+    pub fn store(
+        &mut self,
+        chunks: &[StoredChunk],
+        registry: &BlockRegistry,
+    ) -> Result<(), SaveError> {
+        let mut by_region: HashMap<IVec2, Vec<&StoredChunk>> = HashMap::new();
+        for stored in chunks {
+            by_region
+                .entry(region_of(stored.pos))
+                .or_default()
+                .push(stored);
+        }
+        fs::create_dir_all(&self.dir)?;
+        for (region, chunks) in by_region {
+            // chunks written with other ids have to be brought over to ours
+            let append = self.index(region, registry)?.is_some_and(|index| {
+                index.same_ids() && index.entries + chunks.len() <= MAX_ENTRIES
+            });
+            if append {
+                self.append(region, &chunks)?;
+            } else {
+                self.rewrite(region, &chunks, registry)?;
+            }
+        }
+        Ok(())
+    }
+    /// adds chunks to the end of a region file written with our ids
+    fn append(&mut self, region: IVec2, chunks: &[&StoredChunk]) -> Result<(), SaveError> {
+        let path = self.path(region);
+        let index = self.index.get_mut(&region).unwrap();
+        let mut data = Vec::new();
+        let mut slots = Vec::new();
+        for stored in chunks {
+            let chunk = encode_stored(stored)?;
+            write_entry(&mut data, stored.pos, &chunk);
+            slots.push((
+                stored.pos,
+                (index.end + (data.len() - chunk.len()) as u64, chunk.len()),
+            ));
+        }
+        let mut file = fs::OpenOptions::new().write(true).open(path)?;
+        // drops whatever is left of a chunk that was cut short
+        file.set_len(index.end)?;
+        file.seek(SeekFrom::Start(index.end))?;
+        file.write_all(&data)?;
+        index.slots.extend(slots);
+        index.entries += chunks.len();
+        index.end += data.len() as u64;
+        Ok(())
+    }
+    /// writes a region file anew with the last copy of every chunk it held and the new chunks
+    fn rewrite(
+        &mut self,
+        region: IVec2,
+        chunks: &[&StoredChunk],
+        registry: &BlockRegistry,
+    ) -> Result<(), SaveError> {
+        let path = self.path(region);
+        let mut entries: Vec<(IVec2, Vec<u8>)> = Vec::new();
+        if let Some(old) = self.index.get(&region) {
+            let same_ids = old.same_ids();
+            let file = fs::read(&path)?;
+            for (pos, &(start, len)) in &old.slots {
+                if chunks.iter().any(|c| c.pos == *pos) {
+                    continue;
+                }
+                let start = start as usize;
+                let data = file
+                    .get(start..start + len)
+                    .ok_or(SaveError::Corrupt("chunk cut short"))?;
+                let data = if same_ids {
+                    data.to_vec()
+                } else {
+                    encode_stored(&decode_stored(*pos, data, &old.ids, old.version)?)?
+                };
+                entries.push((*pos, data));
+            }
+        }
+        for stored in chunks {
+            entries.push((stored.pos, encode_stored(stored)?));
+        }
+        let mut file = Vec::new();
+        file.extend(MAGIC);
+        file.extend(FORMAT_VERSION.to_le_bytes());
+        write_names(&mut file, registry)?;
+        file.extend((entries.len() as u32).to_le_bytes());
+        let mut slots = HashMap::new();
+        for (pos, data) in &entries {
+            write_entry(&mut file, *pos, data);
+            slots.insert(*pos, ((file.len() - data.len()) as u64, data.len()));
+        }
+        // write next to the old file first so a crash can't leave half a region behind
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, &file)?;
+        fs::rename(tmp, path)?;
+        let index = RegionIndex {
+            version: FORMAT_VERSION,
+            // the file was just written with our ids
+            ids: std::array::from_fn(|i| Some(i as BlockId)),
+            slots,
+            entries: entries.len(),
+            end: file.len() as u64,
+        };
+        self.index.insert(region, index);
+        Ok(())
+    }
+    /// deletes every region file, for when a new world starts in the same place
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.index.clear();
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+    /// replaces the region files of `to` with copies of these ones
+    pub fn copy_to(&self, to: &mut RegionStore) -> io::Result<()> {
+        to.clear()?;
+        let entries = match fs::read_dir(&self.dir) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            entries => entries?,
+        };
+        fs::create_dir_all(&to.dir)?;
+        for entry in entries {
+            let entry = entry?;
+            fs::copy(entry.path(), to.dir.join(entry.file_name()))?;
+        }
+        Ok(())
+    }
+}
+#[test]
+fn region_store_test() {
+    use crate::{registry::STONE, state::BlockState};
+    use macroquad::math::ivec3;
+    let dir = std::env::temp_dir().join(format!("isow-region-test-{}", std::process::id()));
+    let mut store = RegionStore::new(&dir);
+    let registry = BlockRegistry::default();
+    let stored = |x: i32, z: i32| {
+        let mut chunk = Chunk::new();
+        chunk.set(ivec3(1, 2, z), BlockState::new(STONE));
+        StoredChunk {
+            pos: ivec2(x, 0),
+            chunk,
+            block_data: vec![(ivec3(x * 16, 0, 0), vec![1, 2, 3])],
+        }
+    };
+    assert!(store.load(ivec2(0, 0), &registry).unwrap().is_none());
+    // two regions written at once, then one chunk of the first replaced
+    store
+        .store(&[stored(0, 1), stored(1, 2), stored(-1, 3)], &registry)
+        .unwrap();
+    store.store(&[stored(1, 4)], &registry).unwrap();
+    for (x, z) in [(0, 1), (1, 4), (-1, 3)] {
+        let back = store.load(ivec2(x, 0), &registry).unwrap().unwrap();
+        assert_eq!(back.chunk.get(ivec3(1, 2, z)).id, STONE);
+        assert_eq!(back.chunk.blocks().count(), 1);
+        assert_eq!(back.block_data, stored(x, z).block_data);
+    }
+    assert!(store.load(ivec2(2, 0), &registry).unwrap().is_none());
+    // a replaced chunk is only added to the end of the file, a store opened
+    // later finds the last copy, also when a crash cut the end short
+    let path = store.path(IVec2::ZERO);
+    let len = fs::metadata(&path).unwrap().len();
+    store.store(&[stored(1, 5)], &registry).unwrap();
+    let grown = fs::metadata(&path).unwrap().len();
+    assert!(grown - len < len);
+    let file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    (&file).write_all(&[1, 0, 0, 0, 0]).unwrap();
+    let mut reopened = RegionStore::new(&dir);
+    let back = reopened.load(ivec2(1, 0), &registry).unwrap().unwrap();
+    assert_eq!(back.chunk.get(ivec3(1, 2, 5)).id, STONE);
+    reopened.store(&[stored(1, 6)], &registry).unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().len(), grown + grown - len);
+    // with too many old copies the file is written anew
+    for _ in 0..MAX_ENTRIES {
+        reopened.store(&[stored(0, 1)], &registry).unwrap();
+    }
+    assert!(reopened.index[&IVec2::ZERO].entries <= MAX_ENTRIES);
+    let mut store = RegionStore::new(&dir);
+    for (x, z) in [(0, 1), (1, 6)] {
+        let back = store.load(ivec2(x, 0), &registry).unwrap().unwrap();
+        assert_eq!(back.chunk.get(ivec3(1, 2, z)).id, STONE);
+    }
+    // a copy holds the same chunks and replaces whatever the copy had before
+    let mut copy = RegionStore::new(dir.with_extension("copy"));
+    copy.store(&[stored(5, 5)], &registry).unwrap();
+    store.copy_to(&mut copy).unwrap();
+    assert!(copy.load(ivec2(5, 0), &registry).unwrap().is_none());
+    assert_eq!(
+        copy.load(ivec2(-1, 0), &registry)
+            .unwrap()
+            .unwrap()
+            .block_data,
+        stored(-1, 3).block_data
+    );
+    store.clear().unwrap();
+    assert!(!dir.exists());
+    store.copy_to(&mut copy).unwrap();
+    assert!(copy.load(ivec2(-1, 0), &registry).unwrap().is_none());
+}
@@ -0,0 +1,315 @@
+/****************************************
+   Block Registry
+   every block id the world stores is
+   described here, anything that needs to
+   know how a block behaves asks the registry
+****************************************/
+use macroquad::math::IVec3;
+
+use crate::{
+    block_entity::BlockEntity,
+    fluid::{flow_update, FluidDef},
+    power::Power,
+    state::BlockState,
+    update::{fall, grow_crop, spread_grass, FALL_DELAY},
+    world::World,
+};
+
+/// id of a block as it's stored in the world
+pub type BlockId = u8;
+
+pub const AIR: BlockId = 0;
+pub const STONE: BlockId = 1;
+pub const DIRT: BlockId = 2;
+pub const GRASS: BlockId = 3;
+pub const SMOOTH_STONE: BlockId = 4;
+pub const TILE: BlockId = 5;
+pub const GRAVEL: BlockId = 6;
+pub const MACHINE: BlockId = 7;
+pub const SAND: BlockId = 8;
+pub const LOG: BlockId = 9;
+pub const LEAVES: BlockId = 10;
+pub const WHEAT: BlockId = 11;
+#[allow(dead_code)]
+pub const WATER: BlockId = 12;
+#[allow(dead_code)]
+pub const LAVA: BlockId = 13;
+#[allow(dead_code)]
+pub const WIRE: BlockId = 14;
+pub const GENERATOR: BlockId = 15;
+
+/// what a block does when it's update comes up, see [`crate::update`]
+pub type UpdateFn = fn(&mut World, IVec3, BlockState);
+/// what a block does when a random tick hits it, `roll` is a random number to decide with
+pub type RandomTickFn = fn(&mut World, IVec3, BlockState, u64);
+
+/// definition of a single block type
+#[derive(Debug, Clone)]
+pub struct BlockDef {
+    /// unique name of the block, used to look blocks up independent of their id
+    pub name: &'static str,
+    /// png bytes of the isometric tile drawn for this block
+    pub texture: &'static [u8],
+    /// whether blocks behind this one can be seen through it
+    pub transparent: bool,
+    /// whether entities collide with this block
+    pub solid: bool,
+    /// light level emitted by this block (0-15)
+    pub light_emission: u8,
+    /// seconds it takes to break this block
+    pub hardness: f32,
+    /// whether the player can pick and place this block
+    pub placeable: bool,
+    /// whether the block keeps a [`crate::state::Facing`] in it's state when placed
+    pub rotatable: bool,
+    /// how the block flows if it's a fluid
+    pub fluid: Option<FluidDef>,
+    /// whether the block falls down when there's nothing solid under it
+    pub gravity: bool,
+    /// whether the block grows in stages, it's drawn as tall as it's grown
+    pub crop: bool,
+    /// run a few ticks after the block or one next to it changed
+    pub on_update: Option<UpdateFn>,
+    /// ticks between a change and `on_update`
+    pub update_delay: u64,
+    /// run when a random tick hits the block
+    pub on_random_tick: Option<RandomTickFn>,
+    /// makes the data the block owns, see [`crate::block_entity`]
+    pub block_entity: Option<fn() -> BlockEntity>,
+    /// the part the block plays in power networks, see [`crate::power`]
+    pub power: Option<Power>,
+}
+impl BlockDef {
+    /// an opaque, solid and placeable block with the given look
+    pub const fn new(name: &'static str, texture: &'static [u8]) -> Self {
+        Self {
+            name,
+            texture,
+            transparent: false,
+            solid: true,
+            light_emission: 0,
+            hardness: 1.0,
+            placeable: true,
+            rotatable: false,
+            fluid: None,
+            gravity: false,
+            crop: false,
+            on_update: None,
+            update_delay: 1,
+            on_random_tick: None,
+            block_entity: None,
+            power: None,
+        }
+    }
+    pub const fn hardness(mut self, hardness: f32) -> Self {
+        self.hardness = hardness;
+        self
+    }
+    pub const fn light_emission(mut self, level: u8) -> Self {
+        self.light_emission = level;
+        self
+    }
+    pub const fn transparent(mut self) -> Self {
+        self.transparent = true;
+        self
+    }
+    pub const fn non_solid(mut self) -> Self {
+        self.solid = false;
+        self
+    }
+    pub const fn unplaceable(mut self) -> Self {
+        self.placeable = false;
+        self
+    }
+    pub const fn rotatable(mut self) -> Self {
+        self.rotatable = true;
+        self
+    }
+    pub const fn on_update(mut self, update: UpdateFn, delay: u64) -> Self {
+        self.on_update = Some(update);
+        self.update_delay = delay;
+        self
+    }
+    pub const fn on_random_tick(mut self, tick: RandomTickFn) -> Self {
+        self.on_random_tick = Some(tick);
+        self
+    }
+    pub const fn block_entity(mut self, create: fn() -> BlockEntity) -> Self {
+        self.block_entity = Some(create);
+        self
+    }
+    pub const fn power(mut self, power: Power) -> Self {
+        self.power = Some(power);
+        self
+    }
+    pub const fn gravity(mut self) -> Self {
+        self.gravity = true;
+        self.on_update(fall, FALL_DELAY)
+    }
+    /// a crop growing on random ticks
+    pub const fn crop(mut self) -> Self {
+        self.crop = true;
+        self.on_random_tick(grow_crop)
+    }
+    /// a see-through, non solid fluid flowing every `delay` ticks, see [`FluidDef`]
+    pub const fn fluid(mut self, falloff: u8, delay: u64) -> Self {
+        self.fluid = Some(FluidDef { falloff });
+        self.transparent().non_solid().on_update(flow_update, delay)
+    }
+}
+
+/// maps block ids to their definitions, the id of a block is the order it was registered in
+pub struct BlockRegistry {
+    defs: Vec<BlockDef>,
+}
+impl BlockRegistry {
+    /// a registry holding nothing but air
+    pub fn new() -> Self {
+        Self {
+            defs: vec![BlockDef::new("air", include_bytes!("../empty.png"))
+                .transparent()
+                .non_solid()
+                .unplaceable()
+                .hardness(0.)],
+        }
+    }
+    /// adds a block and returns the id it was given
+    pub fn register(&mut self, def: BlockDef) -> BlockId {
+        assert!(
+            self.by_name(def.name).is_none(),
+            "block {} is already registered",
+            def.name
+        );
+        let id = BlockId::try_from(self.defs.len()).expect("ran out of block ids");
+        self.defs.push(def);
+        id
+    }
+    /// definition of a block, unknown ids are treated as air
+    pub fn get(&self, id: BlockId) -> &BlockDef {
+        self.defs
+            .get(id as usize)
+            .unwrap_or(&self.defs[AIR as usize])
+    }
+    pub fn by_name(&self, name: &str) -> Option<BlockId> {
+        self.defs
+            .iter()
+            .position(|d| d.name == name)
+            .map(|i| i as BlockId)
+    }
+    pub fn is_solid(&self, id: BlockId) -> bool {
+        self.get(id).solid
+    }
+    pub fn is_transparent(&self, id: BlockId) -> bool {
+        self.get(id).transparent
+    }
+    pub fn is_fluid(&self, id: BlockId) -> bool {
+        self.get(id).fluid.is_some()
+    }
+    /// every block with it's id, in id order
+    pub fn iter(&self) -> impl Iterator<Item = (BlockId, &BlockDef)> {
+        self.defs.iter().enumerate().map(|(i, d)| (i as BlockId, d))
+    }
+    /// ids of blocks the player can pick and place, in id order
+    pub fn placeable(&self) -> impl Iterator<Item = BlockId> + '_ {
+        self.iter().filter(|(_, d)| d.placeable).map(|(i, _)| i)
+    }
+}
+impl Default for BlockRegistry {
+    /// the blocks that ship with the game
+    fn default() -> Self {
+        let mut r = Self::new();
+        r.register(BlockDef::new("stone", include_bytes!("../tile_stone.png")).hardness(1.5));
+        r.register(BlockDef::new("dirt", include_bytes!("../tile_dirt.png")).hardness(0.5));
+        r.register(
+            BlockDef::new("grass", include_bytes!("../tile_grass.png"))
+                .hardness(0.6)
+                .on_random_tick(spread_grass),
+        );
+        r.register(
+            BlockDef::new("smooth_stone", include_bytes!("../tile_stone_smooth.png")).hardness(2.0),
+        );
+        r.register(BlockDef::new("tile", include_bytes!("../tile.png")));
+        r.register(
+            BlockDef::new("gravel", include_bytes!("../tile_gravel.png"))
+                .hardness(0.6)
+                .gravity(),
+        );
+        r.register(
+            BlockDef::new("machine", include_bytes!("../tile_machine.png"))
+                .hardness(3.0)
+                .light_emission(7)
+                .rotatable()
+                .block_entity(BlockEntity::machine)
+                .power(Power::Consumer),
+        );
+        r.register(
+            BlockDef::new("sand", include_bytes!("../tile_sand.png"))
+                .hardness(0.5)
+                .gravity(),
+        );
+        r.register(BlockDef::new("log", include_bytes!("../tile_log.png")).hardness(2.0));
+        r.register(
+            BlockDef::new("leaves", include_bytes!("../tile_leaves.png"))
+                .hardness(0.2)
+                .transparent(),
+        );
+        r.register(
+            BlockDef::new("wheat", include_bytes!("../wheat.png"))
+                .hardness(0.)
+                .transparent()
+                .non_solid()
+                .crop(),
+        );
+        r.register(
+            BlockDef::new("water", include_bytes!("../tile_water.png"))
+                .hardness(0.)
+                .fluid(1, 5),
+        );
+        r.register(
+            BlockDef::new("lava", include_bytes!("../tile_lava.png"))
+                .hardness(0.)
+                .light_emission(15)
+                .fluid(2, 15),
+        );
+        r.register(
+            BlockDef::new("wire", include_bytes!("../tile_wire.png"))
+                .hardness(0.5)
+                .power(Power::Conductor),
+        );
+        r.register(
+            BlockDef::new("generator", include_bytes!("../tile_generator.png"))
+                .hardness(3.0)
+                .light_emission(4)
+                .power(Power::Source(15)),
+        );
+        r
+    }
+}
+#[test]
+fn default_registry_test() {
+    let r = BlockRegistry::default();
+    for (id, name) in [
+        (AIR, "air"),
+        (STONE, "stone"),
+        (DIRT, "dirt"),
+        (GRASS, "grass"),
+        (SMOOTH_STONE, "smooth_stone"),
+        (TILE, "tile"),
+        (GRAVEL, "gravel"),
+        (MACHINE, "machine"),
+        (SAND, "sand"),
+        (LOG, "log"),
+        (LEAVES, "leaves"),
+        (WHEAT, "wheat"),
+        (WATER, "water"),
+        (LAVA, "lava"),
+        (WIRE, "wire"),
+        (GENERATOR, "generator"),
+    ] {
+        assert_eq!(r.by_name(name), Some(id));
+    }
+    assert!(!r.is_solid(AIR) && r.is_transparent(AIR));
+    assert!(r.is_solid(STONE) && !r.is_transparent(STONE));
+    assert!(r.is_fluid(WATER) && !r.is_solid(WATER) && !r.is_fluid(STONE));
+    assert_eq!(r.placeable().next(), Some(STONE));
+}
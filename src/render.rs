@@ -27,12 +27,15 @@ pub fn draw_tile_color(x: f32, y: f32, tile_size: (f32, f32), texture: &Texture2
 pub struct DrawTilesParams {
     pub margin: (f32, f32),
     pub color: Color,
+    /// mirror the tile horizontally
+    pub flip_x: bool,
 }
 impl Default for DrawTilesParams {
     fn default() -> Self {
         Self {
             margin: Default::default(),
             color: WHITE,
+            flip_x: false,
         }
     }
 }
@@ -57,6 +60,7 @@ pub fn draw_tile_ex(
                 )
                     .into(),
             ),
+            flip_x: options.flip_x,
             ..Default::default()
         },
     );
@@ -0,0 +1,399 @@
+/****************************************
+   World Saves
+
+   A save is a little-endian binary file:
+
+   | field       | type             | notes                                   |
+   |-------------|------------------|-----------------------------------------|
+   | magic       | `[u8; 4]`        | always `ISOW`                           |
+   | version     | `u16`            | [`FORMAT_VERSION`]                      |
+   | height      | `u16`            | world height in blocks                  |
+   | chunk size  | `u16`            | chunk width and depth in blocks         |
+   | player      | `[f32; 3]`       | player position x, y, z                 |
+   | seed        | `u64`            | since version 4, generates new chunks   |
+   | names       | `u16` + entries  | id table: `u8` id, `u8` len, utf8 name  |
+   | chunks      | `u32` + entries  | see below                               |
+   | block data  | `u32` + entries  | since version 3, see below              |
+
+   every chunk is stored as it's chunk coordinates (`i32` x, `i32` y) followed by
+   a `u32` count of runs and the runs themselves, a run is a `u16` length and a
+   `u16` block state (id in the low byte, state bits in the high byte) repeated
+   that many times, runs cover the chunk in x, then y, then z order.
+   since version 2 the runs are followed by one `u8` biome id per column of the
+   chunk in x, then y order, version 1 saves load with every column as plains.
+
+   every block entity is stored as the position of it's block (`i32` x, y, z)
+   followed by a `u16` length and that many bytes of data, what the data means
+   is up to the entity (see [`crate::block_entity::BlockEntity::encode`]),
+   blocks owning an entity that isn't in the save get a fresh one.
+
+   ids inside the chunks refer to the name table so a save keeps working when
+   blocks get registered in a different order.
+
+   a save only holds the chunks that were loaded, the others are in region
+   files next to it (see [`crate::region`]) or weren't generated yet.
+****************************************/
+use std::{
+    error::Error,
+    fmt,
+    io::{self, Read, Write},
+};
+
+use macroquad::math::{ivec2, ivec3, vec3, IVec3, Vec3};
+
+use crate::{
+    biome::Biome,
+    chunk::{Chunk, CHUNK_SIZE, HEIGHT},
+    registry::{BlockId, BlockRegistry},
+    state::BlockState,
+    world::World,
+};
+
+const MAGIC: &[u8; 4] = b"ISOW";
+/// version of the save format written by this build
+pub const FORMAT_VERSION: u16 = 4;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    /// the data does not start with the save magic
+    NotASave,
+    /// the save was written by a newer (or unknown) version of the format
+    UnsupportedVersion(u16),
+    /// the save was made for a world with a different height or chunk size
+    Dimensions {
+        height: u16,
+        chunk_size: u16,
+    },
+    /// the save uses a block this build does not know about
+    UnknownBlock(String),
+    /// the data is not a valid save
+    Corrupt(&'static str),
+}
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "{e}"),
+            SaveError::NotASave => write!(f, "not a world save"),
+            SaveError::UnsupportedVersion(v) => write!(f, "unsupported save version {v}"),
+            SaveError::Dimensions { height, chunk_size } => write!(
+                f,
+                "save is for a world of height {height} and chunk size {chunk_size}"
+            ),
+            SaveError::UnknownBlock(name) => write!(f, "unknown block {name}"),
+            SaveError::Corrupt(what) => write!(f, "corrupt save: {what}"),
+        }
+    }
+}
+impl Error for SaveError {}
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+pub(crate) fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    let mut b = [0; 1];
+    r.read_exact(&mut b)?;
+    Ok(b[0])
+}
+pub(crate) fn read_u16(r: &mut impl Read) -> io::Result<u16> {
+    let mut b = [0; 2];
+    r.read_exact(&mut b)?;
+    Ok(u16::from_le_bytes(b))
+}
+pub(crate) fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+pub(crate) fn read_i32(r: &mut impl Read) -> io::Result<i32> {
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(i32::from_le_bytes(b))
+}
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut b = [0; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+fn read_f32(r: &mut impl Read) -> io::Result<f32> {
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(f32::from_le_bytes(b))
+}
+
+/// run length encodes a chunk as (length, state) pairs
+fn encode_chunk(chunk: &Chunk) -> Vec<(u16, BlockState)> {
+    let mut runs: Vec<(u16, BlockState)> = Vec::new();
+    for s in chunk.states() {
+        match runs.last_mut() {
+            Some((n, last)) if *last == s && *n < u16::MAX => *n += 1,
+            _ => runs.push((1, s)),
+        }
+    }
+    runs
+}
+
+/// writes the id table of a registry, see [`crate::save`]
+pub(crate) fn write_names<W: Write>(w: &mut W, registry: &BlockRegistry) -> Result<(), SaveError> {
+    let names: Vec<_> = registry.iter().collect();
+    w.write_all(&(names.len() as u16).to_le_bytes())?;
+    for (id, def) in names {
+        w.write_all(&[id, def.name.len() as u8])?;
+        w.write_all(def.name.as_bytes())?;
+    }
+    Ok(())
+}
+/// reads an id table back, mapping the ids it lists to the ids of `registry`
+pub(crate) fn read_names<R: Read>(
+    r: &mut R,
+    registry: &BlockRegistry,
+) -> Result<[Option<BlockId>; 256], SaveError> {
+    let mut ids = [None; 256];
+    for _ in 0..read_u16(r)? {
+        let id = read_u8(r)?;
+        let mut name = vec![0; read_u8(r)? as usize];
+        r.read_exact(&mut name)?;
+        let name = String::from_utf8(name).map_err(|_| SaveError::Corrupt("block name"))?;
+        match registry.by_name(&name) {
+            Some(ours) => ids[id as usize] = Some(ours),
+            None => return Err(SaveError::UnknownBlock(name)),
+        }
+    }
+    Ok(ids)
+}
+/// writes the runs and biomes of a chunk, it's coordinates are up to the caller
+pub(crate) fn write_chunk<W: Write>(w: &mut W, chunk: &Chunk) -> Result<(), SaveError> {
+    let runs = encode_chunk(chunk);
+    w.write_all(&(runs.len() as u32).to_le_bytes())?;
+    for (n, s) in runs {
+        w.write_all(&n.to_le_bytes())?;
+        w.write_all(&s.to_bits().to_le_bytes())?;
+    }
+    let biomes: Vec<u8> = chunk.biomes().iter().map(|b| *b as u8).collect();
+    w.write_all(&biomes)?;
+    Ok(())
+}
+/// reads back what [`write_chunk`] wrote with a save of format `version`
+pub(crate) fn read_chunk<R: Read>(
+    r: &mut R,
+    ids: &[Option<BlockId>; 256],
+    version: u16,
+) -> Result<Chunk, SaveError> {
+    let mut states = Vec::with_capacity(Chunk::VOLUME);
+    for _ in 0..read_u32(r)? {
+        let n = read_u16(r)? as usize;
+        let s = BlockState::from_bits(read_u16(r)?);
+        let id = ids[s.id as usize].ok_or(SaveError::Corrupt("block id not in table"))?;
+        if states.len() + n > Chunk::VOLUME {
+            return Err(SaveError::Corrupt("chunk overflows"));
+        }
+        states.extend(std::iter::repeat_n(BlockState { id, ..s }, n));
+    }
+    if states.len() != Chunk::VOLUME {
+        return Err(SaveError::Corrupt("chunk is incomplete"));
+    }
+    let mut chunk = Chunk::from_states(states);
+    if version >= 2 {
+        let mut biomes = [0; (CHUNK_SIZE * CHUNK_SIZE) as usize];
+        r.read_exact(&mut biomes)?;
+        for (i, b) in biomes.into_iter().enumerate() {
+            let b = Biome::from_id(b).ok_or(SaveError::Corrupt("unknown biome"))?;
+            chunk.set_biome(i as i32 % CHUNK_SIZE, i as i32 / CHUNK_SIZE, b);
+        }
+    }
+    Ok(chunk)
+}
+/// writes the data of a block entity with the position of it's block
+pub(crate) fn write_block_data<W: Write>(
+    w: &mut W,
+    pos: IVec3,
+    data: &[u8],
+) -> Result<(), SaveError> {
+    for v in pos.to_array() {
+        w.write_all(&v.to_le_bytes())?;
+    }
+    w.write_all(&(data.len() as u16).to_le_bytes())?;
+    w.write_all(data)?;
+    Ok(())
+}
+pub(crate) fn read_block_data<R: Read>(r: &mut R) -> Result<(IVec3, Vec<u8>), SaveError> {
+    let pos = ivec3(read_i32(r)?, read_i32(r)?, read_i32(r)?);
+    let mut data = vec![0; read_u16(r)? as usize];
+    r.read_exact(&mut data)?;
+    Ok((pos, data))
+}
+
+impl World {
+    /// writes the world and the player's position as a save, see [`crate::save`] for the format
+    pub fn save<W: Write>(&self, mut w: W, player_pos: Vec3) -> Result<(), SaveError> {
+        w.write_all(MAGIC)?;
+        w.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        w.write_all(&(HEIGHT as u16).to_le_bytes())?;
+        w.write_all(&(CHUNK_SIZE as u16).to_le_bytes())?;
+        for v in player_pos.to_array() {
+            w.write_all(&v.to_le_bytes())?;
+        }
+        w.write_all(&self.seed().to_le_bytes())?;
+        write_names(&mut w, self.registry())?;
+        w.write_all(&(self.chunks().count() as u32).to_le_bytes())?;
+        for (pos, chunk) in self.chunks() {
+            w.write_all(&pos.x.to_le_bytes())?;
+            w.write_all(&pos.y.to_le_bytes())?;
+            write_chunk(&mut w, chunk)?;
+        }
+        w.write_all(&(self.block_entities().count() as u32).to_le_bytes())?;
+        for (pos, entity) in self.block_entities() {
+            write_block_data(&mut w, pos, &entity.encode())?;
+        }
+        Ok(())
+    }
+    /// reads a world and the player's position back from a save
+    pub fn load<R: Read>(mut r: R) -> Result<(World, Vec3), SaveError> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SaveError::NotASave);
+        }
+        let version = read_u16(&mut r)?;
+        if !(1..=FORMAT_VERSION).contains(&version) {
+            return Err(SaveError::UnsupportedVersion(version));
+        }
+        let height = read_u16(&mut r)?;
+        let chunk_size = read_u16(&mut r)?;
+        if height as i32 != HEIGHT || chunk_size as i32 != CHUNK_SIZE {
+            return Err(SaveError::Dimensions { height, chunk_size });
+        }
+        let player_pos = vec3(read_f32(&mut r)?, read_f32(&mut r)?, read_f32(&mut r)?);
+
+        let mut world = World::new();
+        if version >= 4 {
+            world.set_seed(read_u64(&mut r)?);
+        }
+        // maps ids of the save to ids of this build
+        let ids = read_names(&mut r, world.registry())?;
+        for _ in 0..read_u32(&mut r)? {
+            let pos = ivec2(read_i32(&mut r)?, read_i32(&mut r)?);
+            let chunk = read_chunk(&mut r, &ids, version)?;
+            world.insert_chunk(pos, chunk);
+        }
+        if version >= 3 {
+            for _ in 0..read_u32(&mut r)? {
+                let (pos, data) = read_block_data(&mut r)?;
+                world
+                    .block_entity_mut(pos)
+                    .ok_or(SaveError::Corrupt("block entity without it's block"))?
+                    .decode(&data)
+                    .ok_or(SaveError::Corrupt("block entity data"))?;
+            }
+        }
+        Ok((world, player_pos))
+    }
+}
+#[test]
+fn save_round_trip_test() {
+    use crate::{block_entity::BlockEntity, registry::GENERATOR, state::Facing};
+    let mut world = World::new();
+    for x in -20..20 {
+        for y in -3..3 {
+            world.set_block(ivec3(x, y, 0), 3).unwrap();
+        }
+    }
+    let machine = BlockState::new(7).with_facing(Facing::North);
+    world.set_state(ivec3(-17, 2, 63), machine).unwrap();
+    world.set_block(ivec3(-17, 2, 62), GENERATOR).unwrap();
+    world.set_biome(-17, 2, Biome::Desert).unwrap();
+    world.set_seed(1234);
+    for _ in 0..42 {
+        world.tick();
+    }
+    let mut data = Vec::new();
+    world.save(&mut data, vec3(1.5, -2., 1.)).unwrap();
+
+    let (loaded, player) = World::load(data.as_slice()).unwrap();
+    assert_eq!(player, vec3(1.5, -2., 1.));
+    assert_eq!(loaded.seed(), 1234);
+    assert_eq!(loaded.get_state(ivec3(-17, 2, 63)), machine);
+    let BlockEntity::Machine(m) = loaded.block_entity(ivec3(-17, 2, 63)).unwrap();
+    assert_eq!(m.progress, 42);
+    assert_eq!(loaded.biome_at(-17, 2), Biome::Desert);
+    assert_eq!(loaded.biome_at(-16, 2), Biome::Plains);
+    assert_eq!(loaded.get_block(ivec3(19, -3, 0)), 3);
+    assert_eq!(loaded.get_block(ivec3(20, 0, 0)), 0);
+    assert_eq!(loaded.blocks().count(), world.blocks().count());
+}
+#[test]
+fn load_rejects_bad_saves_test() {
+    assert!(matches!(
+        World::load(&b"NOPE"[..]),
+        Err(SaveError::NotASave)
+    ));
+    let mut data = Vec::new();
+    World::new().save(&mut data, Vec3::ZERO).unwrap();
+    data[4] = 99;
+    assert!(matches!(
+        World::load(data.as_slice()),
+        Err(SaveError::UnsupportedVersion(99))
+    ));
+    data[4] = FORMAT_VERSION as u8;
+    data.truncate(8);
+    assert!(matches!(
+        World::load(data.as_slice()),
+        Err(SaveError::Io(_))
+    ));
+}
+#[test]
+fn load_old_saves_test() {
+    use crate::{
+        block_entity::{BlockEntity, Machine},
+        registry::MACHINE,
+    };
+    // a chunk at (0, 0) with a machine in it's first cell, the save calls it id 9
+    let save = |version: u16| {
+        let mut data = Vec::new();
+        data.extend(MAGIC);
+        data.extend(version.to_le_bytes());
+        data.extend((HEIGHT as u16).to_le_bytes());
+        data.extend((CHUNK_SIZE as u16).to_le_bytes());
+        for v in [1f32, 2., 3.] {
+            data.extend(v.to_le_bytes());
+        }
+        data.extend(2u16.to_le_bytes());
+        data.extend([0, 3]);
+        data.extend(b"air");
+        data.extend([9, 7]);
+        data.extend(b"machine");
+        data.extend(1u32.to_le_bytes());
+        data.extend([0; 8]);
+        data.extend(2u32.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(9u16.to_le_bytes());
+        data.extend((Chunk::VOLUME as u16 - 1).to_le_bytes());
+        data.extend(0u16.to_le_bytes());
+        data
+    };
+    // version 1 has no biomes, block data or seed
+    let (world, player) = World::load(save(1).as_slice()).unwrap();
+    assert_eq!(player, vec3(1., 2., 3.));
+    assert_eq!(world.get_block(IVec3::ZERO), MACHINE);
+    assert_eq!(world.biome_at(5, 5), Biome::Plains);
+    assert_eq!(
+        world.block_entity(IVec3::ZERO),
+        Some(&BlockEntity::Machine(Machine::new()))
+    );
+    assert_eq!(world.seed(), 0);
+
+    // version 3 has biomes and block data but no seed
+    let mut v3 = save(3);
+    v3.extend([Biome::Desert as u8; (CHUNK_SIZE * CHUNK_SIZE) as usize]);
+    v3.extend(0u32.to_le_bytes());
+    let (world, _) = World::load(v3.as_slice()).unwrap();
+    assert_eq!(world.get_block(IVec3::ZERO), MACHINE);
+    assert_eq!(world.biome_at(5, 5), Biome::Desert);
+    assert_eq!(
+        world.block_entity(IVec3::ZERO),
+        Some(&BlockEntity::Machine(Machine::new()))
+    );
+    assert_eq!(world.seed(), 0);
+}
@@ -0,0 +1,334 @@
+/****************************************
+   Chunk Sections
+   a chunk keeps it's blocks in sections 16
+   blocks tall, every section keeps the states
+   it holds in a small palette and the blocks
+   as bit packed indices into it, a section of
+   a single state (all air up in the sky or all
+   stone deep down) keeps nothing else, light
+   is sectioned the same way
+****************************************/
+use crate::{chunk::CHUNK_SIZE, state::BlockState};
+
+/// height of a section in blocks
+pub const SECTION_HEIGHT: i32 = 16;
+/// number of blocks in a section
+pub const SECTION_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * SECTION_HEIGHT) as usize;
+
+/// bits an index needs to point anywhere into a palette of `len` states
+fn bits_for(len: usize) -> u32 {
+    if len <= 1 {
+        0
+    } else {
+        usize::BITS - (len - 1).leading_zeros()
+    }
+}
+
+/// `SECTION_VOLUME` blocks laid out in x, then y, then z order like a chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// the states the indices point at, states stay in here after the last
+    /// block using them is gone until the section gets compacted
+    palette: Vec<BlockState>,
+    /// bits per index, 0 while the palette holds a single state
+    bits: u32,
+    /// the indices packed into words, none of them spans two words
+    data: Box<[u64]>,
+}
+impl Section {
+    /// a section filled with a single state
+    pub fn filled(state: BlockState) -> Self {
+        Self {
+            palette: vec![state],
+            bits: 0,
+            data: Box::new([]),
+        }
+    }
+    /// builds a section out of `SECTION_VOLUME` states
+    pub fn from_states(states: &[BlockState]) -> Self {
+        assert_eq!(states.len(), SECTION_VOLUME);
+        let mut section = Self::filled(states[0]);
+        for (i, s) in states.iter().enumerate() {
+            section.set(i, *s);
+        }
+        section
+    }
+    #[inline]
+    fn index(&self, i: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = 64 / self.bits as usize;
+        let shift = (i % per_word) as u32 * self.bits;
+        ((self.data[i / per_word] >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+    #[inline]
+    fn set_index(&mut self, i: usize, index: usize) {
+        let per_word = 64 / self.bits as usize;
+        let shift = (i % per_word) as u32 * self.bits;
+        let mask = ((1 << self.bits) - 1) << shift;
+        let word = &mut self.data[i / per_word];
+        *word = (*word & !mask) | ((index as u64) << shift);
+    }
+    /// packs `indices` again with as many bits as the palette needs
+    fn pack(&mut self, indices: impl Iterator<Item = usize>) {
+        self.bits = bits_for(self.palette.len());
+        if self.bits == 0 {
+            self.data = Box::new([]);
+            return;
+        }
+        let words = SECTION_VOLUME.div_ceil(64 / self.bits as usize);
+        self.data = vec![0; words].into_boxed_slice();
+        for (i, index) in indices.enumerate() {
+            self.set_index(i, index);
+        }
+    }
+    pub fn get(&self, i: usize) -> BlockState {
+        self.palette[self.index(i)]
+    }
+    /// sets a block, the indices get wider once the palette outgrows them
+    pub fn set(&mut self, i: usize, state: BlockState) {
+        let index = match self.palette.iter().position(|s| *s == state) {
+            Some(index) => index,
+            None => {
+                // make room from states nobody uses anymore before growing
+                if self.bits > 0 && self.palette.len() == 1 << self.bits {
+                    self.compact();
+                }
+                self.palette.push(state);
+                if self.palette.len() > 1 << self.bits {
+                    let indices: Vec<usize> = (0..SECTION_VOLUME).map(|i| self.index(i)).collect();
+                    self.pack(indices.into_iter());
+                }
+                self.palette.len() - 1
+            }
+        };
+        if self.bits > 0 {
+            self.set_index(i, index);
+        }
+    }
+    /// drops the states no block uses anymore from the palette,
+    /// a section left with a single state goes back to storing nothing else
+    pub fn compact(&mut self) {
+        if self.bits == 0 {
+            return;
+        }
+        let mut used = vec![false; self.palette.len()];
+        for i in 0..SECTION_VOLUME {
+            used[self.index(i)] = true;
+        }
+        if used.iter().all(|u| *u) {
+            return;
+        }
+        // where every old index ends up
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::new();
+        for (i, s) in self.palette.iter().enumerate() {
+            if used[i] {
+                remap[i] = palette.len();
+                palette.push(*s);
+            }
+        }
+        let indices: Vec<usize> = (0..SECTION_VOLUME).map(|i| remap[self.index(i)]).collect();
+        self.palette = palette;
+        self.pack(indices.into_iter());
+    }
+    /// the state filling the whole section, `None` if it holds more than one
+    pub fn uniform(&self) -> Option<BlockState> {
+        (self.bits == 0).then_some(self.palette[0])
+    }
+    /// every state of the section in order
+    pub fn states(&self) -> impl Iterator<Item = BlockState> + '_ {
+        (0..SECTION_VOLUME).map(|i| self.get(i))
+    }
+    /// bytes the section keeps on the heap
+    #[cfg(test)]
+    pub fn heap_size(&self) -> usize {
+        self.palette.capacity() * size_of::<BlockState>() + self.data.len() * size_of::<u64>()
+    }
+}
+impl Default for Section {
+    fn default() -> Self {
+        Self::filled(BlockState::AIR)
+    }
+}
+/// the packed light of `SECTION_VOLUME` blocks, see [`crate::light`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LightSection {
+    /// every block is lit the same, like open sky or solid rock
+    Uniform(u8),
+    /// the light of every block in the same order as a section's blocks
+    Mixed(Box<[u8]>),
+}
+impl LightSection {
+    pub fn get(&self, i: usize) -> u8 {
+        match self {
+            LightSection::Uniform(light) => *light,
+            LightSection::Mixed(lights) => lights[i],
+        }
+    }
+    pub fn set(&mut self, i: usize, light: u8) {
+        match self {
+            LightSection::Uniform(l) if *l == light => {}
+            LightSection::Uniform(l) => {
+                let mut lights = vec![*l; SECTION_VOLUME].into_boxed_slice();
+                lights[i] = light;
+                *self = LightSection::Mixed(lights);
+            }
+            LightSection::Mixed(lights) => lights[i] = light,
+        }
+    }
+    /// goes back to a single level once every block is lit the same again
+    pub fn compact(&mut self) {
+        if let LightSection::Mixed(lights) = self {
+            if lights.iter().all(|l| *l == lights[0]) {
+                *self = LightSection::Uniform(lights[0]);
+            }
+        }
+    }
+    /// bytes the light keeps on the heap
+    #[cfg(test)]
+    pub fn heap_size(&self) -> usize {
+        match self {
+            LightSection::Uniform(_) => 0,
+            LightSection::Mixed(lights) => lights.len(),
+        }
+    }
+}
+impl Default for LightSection {
+    fn default() -> Self {
+        LightSection::Uniform(0)
+    }
+}
+#[test]
+fn section_test() {
+    let mut section = Section::default();
+    assert_eq!(section.uniform(), Some(BlockState::AIR));
+    assert_eq!(section.heap_size(), size_of::<BlockState>());
+    // the indices widen as more states show up
+    for i in 0..40 {
+        section.set(i * 7, BlockState::new(1).with_data(i as u8));
+    }
+    assert_eq!(section.bits, 6);
+    for i in 0..40 {
+        assert_eq!(section.get(i * 7), BlockState::new(1).with_data(i as u8));
+        assert_eq!(section.get(i * 7 + 1), BlockState::AIR);
+    }
+    // states going away are dropped once the palette is full again
+    for i in 1..40 {
+        section.set(i * 7, BlockState::AIR);
+    }
+    for i in 0..63 {
+        section.set(
+            SECTION_VOLUME - 1 - i,
+            BlockState::new(2).with_data(i as u8),
+        );
+    }
+    assert_eq!((section.palette.len(), section.bits), (65, 7));
+    section.compact();
+    assert_eq!((section.palette.len(), section.bits), (65, 7));
+    // and a section back to a single state stores nothing else
+    let mut section = Section::from_states(&[BlockState::new(1); SECTION_VOLUME]);
+    assert_eq!(section.uniform(), Some(BlockState::new(1)));
+    section.set(5, BlockState::AIR);
+    assert_eq!(section.uniform(), None);
+    section.set(5, BlockState::new(1));
+    section.compact();
+    assert_eq!(section.uniform(), Some(BlockState::new(1)));
+    assert_eq!(section.states().count(), SECTION_VOLUME);
+}
+#[test]
+fn light_section_test() {
+    let mut light = LightSection::Uniform(0xF0);
+    light.set(3, 0xF0);
+    assert_eq!((light.get(3), light.heap_size()), (0xF0, 0));
+    light.set(3, 0x0E);
+    assert_eq!((light.get(3), light.get(4)), (0x0E, 0xF0));
+    assert_eq!(light.heap_size(), SECTION_VOLUME);
+    light.compact();
+    assert_eq!(light.heap_size(), SECTION_VOLUME);
+    light.set(3, 0xF0);
+    light.compact();
+    assert_eq!(light, LightSection::Uniform(0xF0));
+}
+#[test]
+#[ignore = "benchmark, run with `cargo test --release -- --ignored --nocapture`"]
+fn section_benchmark_test() {
+    use std::{hint::black_box, time::Instant};
+
+    use macroquad::math::ivec3;
+
+    use crate::{
+        chunk::{Chunk, HEIGHT},
+        noise::hash3,
+    };
+
+    const READS: usize = 2_000_000;
+    const WRITES: usize = 200_000;
+    // a terrain like chunk: stone, some ore, dirt, grass and air up to the sky
+    let state_at = |x: i32, y: i32, z: i32| match z {
+        0..20 if hash3(0, x, y, z).is_multiple_of(16) => BlockState::new(6),
+        0..20 => BlockState::new(1),
+        20..23 => BlockState::new(2),
+        23 => BlockState::new(3),
+        _ => BlockState::AIR,
+    };
+    let positions: Vec<_> = (0..READS as i32)
+        .map(|i| {
+            let h = hash3(1, i, 0, 0);
+            ivec3(
+                (h % 16) as i32,
+                (h >> 8 & 15) as i32,
+                ((h >> 16) % HEIGHT as u64) as i32,
+            )
+        })
+        .collect();
+    // the flat storage chunks used before, a state per block
+    let mut flat = vec![BlockState::AIR; Chunk::VOLUME];
+    let flat_index = |p: macroquad::math::IVec3| (p.z * 256 + p.y * 16 + p.x) as usize;
+    let mut chunk = Chunk::new();
+    for z in 0..HEIGHT {
+        for y in 0..16 {
+            for x in 0..16 {
+                let p = ivec3(x, y, z);
+                flat[flat_index(p)] = state_at(x, y, z);
+                chunk.set(p, state_at(x, y, z));
+            }
+        }
+    }
+
+    let start = Instant::now();
+    for p in &positions {
+        black_box(flat[flat_index(*p)]);
+    }
+    let flat_reads = start.elapsed();
+    let start = Instant::now();
+    for p in &positions {
+        black_box(chunk.get(*p));
+    }
+    let section_reads = start.elapsed();
+
+    let start = Instant::now();
+    for (i, p) in positions.iter().take(WRITES).enumerate() {
+        flat[flat_index(*p)] = BlockState::new((i % 8) as u8);
+    }
+    let flat_writes = start.elapsed();
+    let start = Instant::now();
+    for (i, p) in positions.iter().take(WRITES).enumerate() {
+        chunk.set(*p, BlockState::new((i % 8) as u8));
+    }
+    let section_writes = start.elapsed();
+
+    println!("{READS} reads: flat {flat_reads:?}, sections {section_reads:?}");
+    println!("{WRITES} writes: flat {flat_writes:?}, sections {section_writes:?}");
+    println!(
+        "block storage of a terrain chunk: flat {} bytes, sections {} bytes",
+        Chunk::VOLUME * size_of::<BlockState>(),
+        Chunk::from_states(
+            (0..Chunk::VOLUME as i32)
+                .map(|i| state_at(i % 16, i / 16 % 16, i / 256))
+                .collect()
+        )
+        .heap_size()
+    );
+}
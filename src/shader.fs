@@ -5,6 +5,7 @@ precision lowp float;
 varying vec2 uv;
 varying lowp vec4 color;
 varying lowp float dist;
+varying highp vec2 world_xy; // position of the fragment in 2d world space
 
 uniform sampler2D Texture;
 uniform vec2 resolution; // screen size in pixels
@@ -18,6 +19,13 @@ uniform lowp vec3 block_world_pos; // position in the world (x,y,z) of the entir
 uniform int player_hidble;
 uniform int block_behind_player; // whether if block is behind the player
 uniform int block_over_top; // id not bool
+uniform highp vec2 tile_origin; // top left corner of the tile being drawn in 2d world space
+uniform highp vec2 tile_size;
+uniform float tile_cut; // how far (in blocks) the tile is sunk into it's cell, what sticks out below gets cut off
+// brightness of the corners of the visible faces, see occlusion.rs for the order
+uniform vec4 ao_top;
+uniform vec4 ao_left;
+uniform vec4 ao_right;
 
 // compares two tiles to determain which one appears on top
 // if positive $lhs is on top of $rhs
@@ -66,6 +74,32 @@ float player_glass() {
     return a;
 }
 
+// blends the brightness of a face's 4 corners at a point on the face (0-1 on both axes)
+float corners(vec4 c, vec2 t) {
+    t = clamp(t, 0.0, 1.0);
+    return mix(mix(c.x, c.y, t.x), mix(c.z, c.w, t.x), t.y);
+}
+// ambient occlusion of the fragment, the top diamond of a tile takes the upper half
+// and the left and right faces split the rest
+float occlusion() {
+    highp vec2 p = (world_xy - tile_origin) / tile_size;
+    float dx = p.x - 0.5;
+    if (abs(dx) * 2.0 + abs(p.y - 0.25) * 4.0 <= 1.0) {
+        return corners(ao_top, vec2(dx + 2.0 * p.y, 2.0 * p.y - dx));
+    }
+    if (p.x < 0.5) {
+        float a = 2.0 * p.x;
+        return corners(ao_left, vec2(a, (p.y - 0.25 - 0.25 * a) * 2.0));
+    }
+    float b = 2.0 * (1.0 - p.x);
+    return corners(ao_right, vec2(b, (p.y - 0.25 - 0.25 * b) * 2.0));
+}
+
 void main() {
-    gl_FragColor = vec4(1.0, 1.0, 1.0, player_glass()) * color * texture2D(Texture, uv);
+    highp vec2 p = (world_xy - tile_origin) / tile_size;
+    // the bottom edge of a tile is a v going through the bottom corners of it's sides
+    if (p.y + tile_cut * 0.5 > 1.0 - 0.5 * abs(p.x - 0.5)) {
+        discard;
+    }
+    gl_FragColor = vec4(vec3(occlusion()), player_glass()) * color * texture2D(Texture, uv);
 }
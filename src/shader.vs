@@ -7,6 +7,7 @@ attribute vec4 color0;
 varying vec2 uv;
 varying lowp vec4 color;
 varying lowp float dist;
+varying highp vec2 world_xy;
 
 uniform mat4 Model;
 uniform mat4 Projection;
@@ -20,4 +21,5 @@ void main() {
     lowp vec4 pos = Projection * Model * vec4(position, 1);
     gl_Position = pos;
     uv = texcoord;
+    world_xy = position.xy;
 }
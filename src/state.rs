@@ -0,0 +1,161 @@
+/****************************************
+   Block States
+   a block id plus a few bits of data
+   the meaning of the bits is up to the block:
+   orientation, growth stage, fluid level...
+****************************************/
+use std::fmt;
+
+use macroquad::math::Vec2;
+
+use crate::registry::{BlockId, AIR};
+
+/// a block id together with it's state bits, this is what the world stores per voxel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct BlockState {
+    pub id: BlockId,
+    pub data: u8,
+}
+impl BlockState {
+    pub const AIR: BlockState = BlockState::new(AIR);
+    /// a block in it's default state
+    pub const fn new(id: BlockId) -> Self {
+        Self { id, data: 0 }
+    }
+    pub const fn with_data(self, data: u8) -> Self {
+        Self { data, ..self }
+    }
+    /// packs the state into 16 bits, id in the low byte and data in the high byte
+    pub const fn to_bits(self) -> u16 {
+        self.id as u16 | (self.data as u16) << 8
+    }
+    pub const fn from_bits(bits: u16) -> Self {
+        Self {
+            id: bits as u8,
+            data: (bits >> 8) as u8,
+        }
+    }
+    /// direction a rotatable block faces, kept in the lowest 2 bits
+    pub fn facing(self) -> Facing {
+        Facing::from_bits(self.data)
+    }
+    pub fn with_facing(self, facing: Facing) -> Self {
+        self.with_data((self.data & !0b11) | facing as u8)
+    }
+    /// growth stage of a crop (0-7), kept in the lowest 3 bits
+    pub fn stage(self) -> u8 {
+        self.data & 0b111
+    }
+    pub fn with_stage(self, stage: u8) -> Self {
+        self.with_data((self.data & !0b111) | stage.min(7))
+    }
+    /// level of a fluid (0-7), kept in the lowest 3 bits
+    pub fn level(self) -> u8 {
+        self.data & 0b111
+    }
+    pub fn with_level(self, level: u8) -> Self {
+        self.with_data((self.data & !0b111) | level.min(7))
+    }
+    /// whether a fluid flowed here instead of being a source, kept in bit 3
+    pub fn is_flowing(self) -> bool {
+        self.data & 0b1000 != 0
+    }
+    pub fn with_flowing(self, flowing: bool) -> Self {
+        self.with_data((self.data & !0b1000) | (flowing as u8) << 3)
+    }
+    /// signal strength a wire carries (0-15), kept in the lowest 4 bits
+    pub fn signal(self) -> u8 {
+        self.data & 0xF
+    }
+    pub fn with_signal(self, signal: u8) -> Self {
+        self.with_data((self.data & !0xF) | signal.min(15))
+    }
+    /// which half of a block a slab occupies, kept in the highest bit
+    #[allow(dead_code)]
+    pub fn half(self) -> Half {
+        if self.data & 0x80 == 0 {
+            Half::Bottom
+        } else {
+            Half::Top
+        }
+    }
+    #[allow(dead_code)]
+    pub fn with_half(self, half: Half) -> Self {
+        match half {
+            Half::Bottom => self.with_data(self.data & !0x80),
+            Half::Top => self.with_data(self.data | 0x80),
+        }
+    }
+}
+impl From<BlockId> for BlockState {
+    fn from(id: BlockId) -> Self {
+        Self::new(id)
+    }
+}
+impl fmt::Display for BlockState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.id, self.data)
+    }
+}
+
+/// direction along the world's grid a block can face
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Facing {
+    /// towards +x
+    #[default]
+    East = 0,
+    /// towards +y
+    South = 1,
+    /// towards -x
+    West = 2,
+    /// towards -y
+    North = 3,
+}
+impl Facing {
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => Facing::East,
+            1 => Facing::South,
+            2 => Facing::West,
+            _ => Facing::North,
+        }
+    }
+    /// the facing closest to a direction on the xy plane
+    pub fn from_direction(dir: Vec2) -> Self {
+        if dir.x.abs() >= dir.y.abs() {
+            if dir.x >= 0. {
+                Facing::East
+            } else {
+                Facing::West
+            }
+        } else if dir.y >= 0. {
+            Facing::South
+        } else {
+            Facing::North
+        }
+    }
+    /// the next facing when turning a quarter clockwise
+    pub fn rotated(self) -> Self {
+        Self::from_bits(self as u8 + 1)
+    }
+}
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Half {
+    Bottom,
+    Top,
+}
+#[test]
+fn block_state_bits_test() {
+    let s = BlockState::new(7).with_facing(Facing::North);
+    assert_eq!(BlockState::from_bits(s.to_bits()), s);
+    assert_eq!(s.facing(), Facing::North);
+    assert_eq!(s.with_half(Half::Top).facing(), Facing::North);
+    assert_eq!(s.with_half(Half::Top).half(), Half::Top);
+    assert_eq!(BlockState::new(3).with_stage(12).stage(), 7);
+    let f = BlockState::new(3).with_level(5).with_flowing(true);
+    assert!(f.is_flowing() && f.level() == 5);
+    assert!(!f.with_flowing(false).is_flowing());
+    assert_eq!(BlockState::new(14).with_signal(20).signal(), 15);
+    assert_eq!(Facing::North.rotated(), Facing::East);
+}
@@ -0,0 +1,185 @@
+/****************************************
+   Chunk Streaming
+   the world only holds the chunks around the
+   player, chunks coming into range are read
+   from their region file or generated and the
+   ones falling out of range are written back
+****************************************/
+use macroquad::math::IVec2;
+
+use crate::{
+    region::{RegionStore, StoredChunk},
+    save::SaveError,
+    world::World,
+    worldgen::WorldGenerator,
+};
+
+/// chunks closer than this to the player's chunk are loaded
+pub const LOAD_RADIUS: i32 = 3;
+/// chunks further than this are unloaded, a little more than the load radius
+/// so walking back and forth over a border doesn't load and unload the same chunks
+pub const UNLOAD_RADIUS: i32 = LOAD_RADIUS + 1;
+
+/// the chunks an update put into the world and took out of it
+#[derive(Debug, Default)]
+pub struct StreamUpdate {
+    pub loaded: Vec<IVec2>,
+    pub unloaded: Vec<IVec2>,
+}
+
+fn within(chunk: IVec2, center: IVec2, radius: i32) -> bool {
+    (chunk - center).length_squared() <= radius * radius
+}
+
+/// keeps the chunks of a world in range of a position loaded
+pub struct ChunkStreamer<G> {
+    generator: G,
+    regions: RegionStore,
+}
+impl<G: WorldGenerator> ChunkStreamer<G> {
+    pub fn new(generator: G, regions: RegionStore) -> Self {
+        Self { generator, regions }
+    }
+    /// where the chunks out of range are kept
+    pub fn regions(&self) -> &RegionStore {
+        &self.regions
+    }
+    pub fn regions_mut(&mut self) -> &mut RegionStore {
+        &mut self.regions
+    }
+    /// writes the chunks out of range of `center` to disk and loads up to `max_loads`
+    /// of the missing ones in range, closest first
+    pub fn update(
+        &mut self,
+        world: &mut World,
+        center: IVec2,
+        max_loads: usize,
+    ) -> Result<StreamUpdate, SaveError> {
+        world.set_streamed(true);
+        let mut update = StreamUpdate::default();
+        // todo: browsers have no file system, chunks just stay loaded there
+        if !cfg!(target_arch = "wasm32") {
+            update.unloaded = world
+                .chunks()
+                .map(|(c, _)| c)
+                .filter(|c| !within(*c, center, UNLOAD_RADIUS))
+                .collect();
+            self.unload(world, &update.unloaded)?;
+        }
+        let mut missing: Vec<IVec2> = (-LOAD_RADIUS..=LOAD_RADIUS)
+            .flat_map(|y| (-LOAD_RADIUS..=LOAD_RADIUS).map(move |x| center + IVec2::new(x, y)))
+            .filter(|c| within(*c, center, LOAD_RADIUS) && world.chunk(*c).is_none())
+            .collect();
+        missing.sort_by_key(|c| (*c - center).length_squared());
+        for chunk in missing.into_iter().take(max_loads) {
+            self.load(world, chunk)?;
+            update.loaded.push(chunk);
+        }
+        Ok(update)
+    }
+    /// takes chunks out of the world and writes them to their region files,
+    /// they're put back if writing fails
+    fn unload(&mut self, world: &mut World, chunks: &[IVec2]) -> Result<(), SaveError> {
+        if chunks.is_empty() {
+            return Ok(());
+        }
+        let stored: Vec<StoredChunk> = chunks
+            .iter()
+            .map(|c| {
+                let (chunk, entities) = world.remove_chunk(*c).unwrap();
+                StoredChunk {
+                    pos: *c,
+                    chunk,
+                    block_data: entities.iter().map(|(p, e)| (*p, e.encode())).collect(),
+                }
+            })
+            .collect();
+        if let Err(e) = self.regions.store(&stored, world.registry()) {
+            // put them back so the changes aren't lost
+            for s in stored {
+                restore(world, s);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+    /// puts a chunk into the world from it's region file, or generates it if it never was stored
+    fn load(&mut self, world: &mut World, pos: IVec2) -> Result<(), SaveError> {
+        if let Some(stored) = self.regions.load(pos, world.registry())? {
+            restore(world, stored);
+            return Ok(());
+        }
+        // generators write trough a world, a scratch one keeps it from touching the loaded chunks
+        let mut scratch = World::new();
+        self.generator.generate_chunk(&mut scratch, pos);
+        let chunk = scratch
+            .remove_chunk(pos)
+            .map(|(c, _)| c)
+            .unwrap_or_default();
+        world.insert_chunk(pos, chunk);
+        Ok(())
+    }
+}
+/// puts a stored chunk into the world, block entities the data doesn't fit keep a fresh one
+fn restore(world: &mut World, stored: StoredChunk) {
+    world.insert_chunk(stored.pos, stored.chunk);
+    for (pos, data) in stored.block_data {
+        if let Some(entity) = world.block_entity_mut(pos) {
+            entity.decode(&data);
+        }
+    }
+}
+#[test]
+fn streaming_test() {
+    use crate::{
+        biome::Biome,
+        block_entity::BlockEntity,
+        registry::{DIRT, MACHINE, STONE},
+        world::WorldError,
+        worldgen::FlatGenerator,
+    };
+    use macroquad::math::{ivec2, ivec3};
+    let dir = std::env::temp_dir().join(format!("isow-streaming-test-{}", std::process::id()));
+    let mut streamer = ChunkStreamer::new(FlatGenerator { block: STONE }, RegionStore::new(&dir));
+    let mut world = World::new();
+    // chunks come in a few at a time until the whole circle is loaded
+    let update = streamer.update(&mut world, IVec2::ZERO, 5).unwrap();
+    assert_eq!(update.loaded.len(), 5);
+    assert_eq!(update.loaded[0], IVec2::ZERO);
+    while !streamer
+        .update(&mut world, IVec2::ZERO, 5)
+        .unwrap()
+        .loaded
+        .is_empty()
+    {}
+    assert_eq!(world.chunks().count(), 29);
+    assert_eq!(world.get_block(ivec3(-48, 0, 0)), STONE);
+    // changes survive the chunk being written out and read back
+    world.set_block(ivec3(1, 1, 0), DIRT).unwrap();
+    world.set_block(ivec3(2, 2, 1), MACHINE).unwrap();
+    let Some(BlockEntity::Machine(m)) = world.block_entity_mut(ivec3(2, 2, 1)) else {
+        panic!("a machine without it's entity");
+    };
+    m.enabled = false;
+    let update = streamer.update(&mut world, ivec2(10, 0), 100).unwrap();
+    assert!(update.unloaded.contains(&IVec2::ZERO));
+    assert!(world.chunk(IVec2::ZERO).is_none() && world.block_entity(ivec3(2, 2, 1)).is_none());
+    // nothing gets written to chunks that aren't loaded
+    assert_eq!(
+        world.set_block(ivec3(1, 1, 5), STONE),
+        Err(WorldError::NotLoaded(IVec2::ZERO))
+    );
+    assert_eq!(
+        world.set_biome(1, 1, Biome::Desert),
+        Err(WorldError::NotLoaded(IVec2::ZERO))
+    );
+    assert!(!world.is_loaded(IVec2::ZERO) && world.chunk(IVec2::ZERO).is_none());
+    streamer.update(&mut world, IVec2::ZERO, 100).unwrap();
+    assert_eq!(world.get_block(ivec3(1, 1, 0)), DIRT);
+    assert_eq!(world.get_block(ivec3(1, 1, 5)), 0);
+    let Some(BlockEntity::Machine(m)) = world.block_entity(ivec3(2, 2, 1)) else {
+        panic!("the machine lost it's entity");
+    };
+    assert!(!m.enabled);
+    RegionStore::new(&dir).clear().unwrap();
+}
@@ -0,0 +1,409 @@
+/****************************************
+   Structures
+   multi block templates (trees, fields, ruins,
+   houses...) placed by world generation or by
+   the player, a template is placed by it's
+   anchor and can be turned in quarter steps
+****************************************/
+use macroquad::math::{ivec2, ivec3, IVec2, IVec3};
+
+use crate::{
+    biome::Biome,
+    chunk::{chunk_of, CHUNK_SIZE},
+    noise::{hash3, rand3},
+    registry::{
+        BlockRegistry, AIR, DIRT, GENERATOR, GRAVEL, LEAVES, LOG, MACHINE, SMOOTH_STONE, TILE,
+        WHEAT,
+    },
+    state::{BlockState, Facing},
+    world::{World, WorldError},
+};
+
+/// quarter turns around the z axis, clockwise when looking down on the world
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise,
+    Half,
+    CounterClockwise,
+}
+impl Rotation {
+    pub const ALL: [Rotation; 4] = [
+        Rotation::None,
+        Rotation::Clockwise,
+        Rotation::Half,
+        Rotation::CounterClockwise,
+    ];
+    fn turns(self) -> u8 {
+        self as u8
+    }
+    /// the next rotation a quarter turn further
+    pub fn next(self) -> Self {
+        Self::ALL[(self.turns() as usize + 1) % 4]
+    }
+    /// turns an offset around the origin
+    pub fn apply(self, v: IVec3) -> IVec3 {
+        match self {
+            Rotation::None => v,
+            Rotation::Clockwise => ivec3(-v.y, v.x, v.z),
+            Rotation::Half => ivec3(-v.x, -v.y, v.z),
+            Rotation::CounterClockwise => ivec3(v.y, -v.x, v.z),
+        }
+    }
+    /// turns a facing the same way [`Rotation::apply`] turns offsets
+    pub fn apply_facing(self, facing: Facing) -> Facing {
+        (0..self.turns()).fold(facing, |f, _| f.rotated())
+    }
+}
+
+/// flips along one horizontal axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirror {
+    /// swaps +x and -x
+    X,
+    /// swaps +y and -y
+    Y,
+}
+impl Mirror {
+    pub fn apply(self, v: IVec3) -> IVec3 {
+        match self {
+            Mirror::X => v.with_x(-v.x),
+            Mirror::Y => v.with_y(-v.y),
+        }
+    }
+    pub fn apply_facing(self, facing: Facing) -> Facing {
+        match (self, facing) {
+            (Mirror::X, Facing::East) => Facing::West,
+            (Mirror::X, Facing::West) => Facing::East,
+            (Mirror::Y, Facing::South) => Facing::North,
+            (Mirror::Y, Facing::North) => Facing::South,
+            (_, f) => f,
+        }
+    }
+}
+
+/// a template of blocks around an anchor
+#[derive(Debug, Clone)]
+pub struct Structure {
+    pub name: &'static str,
+    /// the offset inside the template that lands on the position it's placed at
+    pub anchor: IVec3,
+    /// template offsets and what goes there, air entries clear whatever was there
+    blocks: Vec<(IVec3, BlockState)>,
+}
+impl Structure {
+    pub fn new(name: &'static str, anchor: IVec3) -> Self {
+        Self {
+            name,
+            anchor,
+            blocks: Vec::new(),
+        }
+    }
+    /// a template out of offsets that are all different
+    pub fn from_blocks(
+        name: &'static str,
+        anchor: IVec3,
+        blocks: Vec<(IVec3, BlockState)>,
+    ) -> Self {
+        Self {
+            name,
+            anchor,
+            blocks,
+        }
+    }
+    pub fn set(&mut self, offset: IVec3, state: impl Into<BlockState>) -> &mut Self {
+        let state = state.into();
+        match self.blocks.iter_mut().find(|(o, _)| *o == offset) {
+            Some(entry) => entry.1 = state,
+            None => self.blocks.push((offset, state)),
+        }
+        self
+    }
+    /// sets every offset of a box between two corners (inclusive)
+    pub fn fill(&mut self, from: IVec3, to: IVec3, state: impl Into<BlockState>) -> &mut Self {
+        let (min, max, state) = (from.min(to), from.max(to), state.into());
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    self.set(ivec3(x, y, z), state);
+                }
+            }
+        }
+        self
+    }
+    /// the same template flipped around it's anchor, blocks the registry calls rotatable
+    /// face the other way too
+    pub fn mirrored(&self, mirror: Mirror, registry: &BlockRegistry) -> Self {
+        let blocks = self
+            .blocks
+            .iter()
+            .map(|(offset, state)| {
+                let offset = self.anchor + mirror.apply(*offset - self.anchor);
+                let state = if registry.get(state.id).rotatable {
+                    state.with_facing(mirror.apply_facing(state.facing()))
+                } else {
+                    *state
+                };
+                (offset, state)
+            })
+            .collect();
+        Self::from_blocks(self.name, self.anchor, blocks)
+    }
+    /// how far the template reaches from it's anchor along x or y
+    pub fn reach(&self) -> i32 {
+        self.blocks
+            .iter()
+            .map(|(o, _)| {
+                let d = *o - self.anchor;
+                d.x.abs().max(d.y.abs())
+            })
+            .max()
+            .unwrap_or(0)
+    }
+    /// world positions and states of every block when placed at `origin` turned by `rotation`,
+    /// blocks the registry calls rotatable are turned with it
+    pub fn blocks_at<'a>(
+        &'a self,
+        origin: IVec3,
+        rotation: Rotation,
+        registry: &'a BlockRegistry,
+    ) -> impl Iterator<Item = (IVec3, BlockState)> + 'a {
+        self.blocks.iter().map(move |(offset, state)| {
+            let pos = origin + rotation.apply(*offset - self.anchor);
+            let state = if registry.get(state.id).rotatable {
+                state.with_facing(rotation.apply_facing(state.facing()))
+            } else {
+                *state
+            };
+            (pos, state)
+        })
+    }
+    /// places the whole template, blocks outside of the world's height are skipped,
+    /// nothing is placed if a chunk it reaches into isn't loaded
+    pub fn place(
+        &self,
+        world: &mut World,
+        origin: IVec3,
+        rotation: Rotation,
+    ) -> Result<(), WorldError> {
+        let mut result = Ok(());
+        let blocks: Vec<_> = self.blocks_at(origin, rotation, world.registry()).collect();
+        if let Some((pos, _)) = blocks.iter().find(|(p, _)| !world.is_loaded(chunk_of(*p))) {
+            return Err(WorldError::NotLoaded(chunk_of(*pos)));
+        }
+        for (pos, state) in blocks {
+            if let Err(e) = world.set_state(pos, state) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+    /// places only the part of the template that falls inside one chunk
+    pub fn place_in_chunk(
+        &self,
+        world: &mut World,
+        origin: IVec3,
+        rotation: Rotation,
+        chunk: IVec2,
+    ) {
+        let blocks: Vec<_> = self.blocks_at(origin, rotation, world.registry()).collect();
+        for (pos, state) in blocks {
+            if chunk_of(pos) == chunk && World::in_bounds(pos) {
+                world.set_state(pos, state).unwrap();
+            }
+        }
+    }
+
+    /// a tree with it's trunk on the anchor
+    pub fn tree() -> Self {
+        let mut s = Self::new("tree", ivec3(2, 2, 0));
+        s.fill(ivec3(0, 0, 3), ivec3(4, 4, 4), LEAVES)
+            .fill(ivec3(1, 1, 5), ivec3(3, 3, 5), LEAVES)
+            .set(ivec3(2, 2, 6), LEAVES)
+            // trim the corners of the crown
+            .set(ivec3(0, 0, 4), AIR)
+            .set(ivec3(4, 0, 4), AIR)
+            .set(ivec3(0, 4, 4), AIR)
+            .set(ivec3(4, 4, 4), AIR)
+            .fill(ivec3(2, 2, 0), ivec3(2, 2, 4), LOG);
+        s
+    }
+    /// rows of wheat on tilled dirt, the anchor sits on the surface
+    pub fn wheat_field() -> Self {
+        let mut s = Self::new("wheat field", ivec3(3, 2, 1));
+        s.fill(ivec3(0, 0, 0), ivec3(6, 4, 0), DIRT).fill(
+            ivec3(0, 0, 1),
+            ivec3(6, 4, 1),
+            BlockState::new(WHEAT).with_stage(7),
+        );
+        // a furrow between the rows
+        s.fill(ivec3(0, 2, 1), ivec3(6, 2, 1), AIR);
+        s
+    }
+    /// broken walls of an old stone building
+    pub fn ruin() -> Self {
+        let mut s = Self::new("ruin", ivec3(3, 3, 1));
+        s.fill(ivec3(0, 0, 0), ivec3(6, 6, 0), GRAVEL)
+            .fill(ivec3(0, 0, 1), ivec3(6, 0, 2), SMOOTH_STONE)
+            .fill(ivec3(0, 0, 1), ivec3(0, 4, 3), SMOOTH_STONE)
+            .fill(ivec3(6, 3, 1), ivec3(6, 6, 1), SMOOTH_STONE)
+            .set(ivec3(3, 0, 2), AIR)
+            .set(ivec3(0, 2, 3), AIR)
+            .set(ivec3(2, 4, 1), GRAVEL);
+        s
+    }
+    /// a small house with a door towards -y and a machine inside, powered by a generator next to it
+    pub fn house() -> Self {
+        let mut s = Self::new("house", ivec3(2, 0, 1));
+        s.fill(ivec3(0, 0, 0), ivec3(4, 4, 0), TILE)
+            .fill(ivec3(0, 0, 1), ivec3(4, 4, 3), SMOOTH_STONE)
+            .fill(ivec3(1, 1, 1), ivec3(3, 3, 3), AIR)
+            .fill(ivec3(2, 0, 1), ivec3(2, 0, 2), AIR)
+            .fill(ivec3(0, 0, 4), ivec3(4, 4, 4), TILE)
+            .set(
+                ivec3(2, 3, 1),
+                BlockState::new(MACHINE).with_facing(Facing::North),
+            )
+            .set(ivec3(3, 3, 1), GENERATOR);
+        s
+    }
+    /// every template that ships with the game
+    pub fn builtin() -> Vec<Structure> {
+        vec![
+            Self::tree(),
+            Self::wheat_field(),
+            Self::ruin(),
+            Self::house(),
+        ]
+    }
+}
+
+/// chunks that could hold part of a structure whose anchor is `reach` blocks or less away from `chunk`
+pub fn chunks_in_reach(chunk: IVec2, reach: i32) -> impl Iterator<Item = IVec2> {
+    let r = (reach + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    (-r..=r).flat_map(move |dy| (-r..=r).map(move |dx| chunk + ivec2(dx, dy)))
+}
+
+/// a structure scattered over the surface of some biomes during generation
+#[derive(Debug, Clone)]
+pub struct Decoration {
+    pub structure: Structure,
+    /// biomes the structure's anchor may land in
+    pub biomes: &'static [Biome],
+    /// average count of tries in a chunk, tries in the wrong biome or on slopes place nothing
+    pub per_chunk: f32,
+    /// how much the ground under the structure may go up or down, `None` allows any slope
+    pub max_slope: Option<i32>,
+}
+impl Decoration {
+    /// the decorations generated by default
+    pub fn defaults() -> Vec<Decoration> {
+        vec![
+            Decoration {
+                structure: Structure::tree(),
+                biomes: &[Biome::Plains],
+                per_chunk: 1.5,
+                max_slope: None,
+            },
+            Decoration {
+                structure: Structure::wheat_field(),
+                biomes: &[Biome::Plains],
+                per_chunk: 0.15,
+                max_slope: Some(1),
+            },
+            Decoration {
+                structure: Structure::ruin(),
+                biomes: &[Biome::Desert, Biome::RockyHighlands, Biome::GravellyShore],
+                per_chunk: 0.1,
+                max_slope: Some(2),
+            },
+            Decoration {
+                structure: Structure::house(),
+                biomes: &[Biome::Plains],
+                per_chunk: 0.08,
+                max_slope: Some(1),
+            },
+        ]
+    }
+    /// columns (as `z = 0` positions) and rotations of the tries anchored in a chunk
+    pub fn tries(&self, seed: u64, chunk: IVec2) -> Vec<(IVec3, Rotation)> {
+        let whole = self.per_chunk.floor() as i32;
+        let count = whole + (rand3(seed, chunk.x, chunk.y, -1) < self.per_chunk.fract()) as i32;
+        (0..count)
+            .map(|i| {
+                let h = hash3(seed, chunk.x, chunk.y, i);
+                let pos = ivec3(
+                    chunk.x * CHUNK_SIZE + (h % CHUNK_SIZE as u64) as i32,
+                    chunk.y * CHUNK_SIZE + ((h >> 8) % CHUNK_SIZE as u64) as i32,
+                    0,
+                );
+                (pos, Rotation::ALL[((h >> 16) % 4) as usize])
+            })
+            .collect()
+    }
+}
+#[test]
+fn rotation_test() {
+    let v = ivec3(2, 1, 3);
+    assert_eq!(Rotation::Clockwise.apply(v), ivec3(-1, 2, 3));
+    let mut r = Rotation::None;
+    let mut u = v;
+    for _ in 0..4 {
+        u = Rotation::Clockwise.apply(u);
+        r = r.next();
+    }
+    assert_eq!((u, r), (v, Rotation::None));
+    assert_eq!(
+        Rotation::Clockwise.apply_facing(Facing::East),
+        Facing::South
+    );
+}
+#[test]
+fn rotatable_blocks_test() {
+    use crate::registry::BlockDef;
+    // any block the registry calls rotatable turns with the template, not just machines
+    let mut registry = BlockRegistry::default();
+    let lamp = registry.register(BlockDef::new("lamp", include_bytes!("../tile.png")).rotatable());
+    let mut s = Structure::new("lamps", IVec3::ZERO);
+    s.set(IVec3::ZERO, BlockState::new(lamp).with_facing(Facing::East))
+        .set(IVec3::X, BlockState::new(TILE).with_facing(Facing::East));
+    let turned: Vec<_> = s
+        .blocks_at(IVec3::ZERO, Rotation::Clockwise, &registry)
+        .collect();
+    assert_eq!(turned[0].1.facing(), Facing::South);
+    assert_eq!(turned[1].1, BlockState::new(TILE).with_facing(Facing::East));
+    let flipped = s.mirrored(Mirror::X, &registry);
+    assert_eq!(flipped.blocks[0].1.facing(), Facing::West);
+    assert_eq!(flipped.blocks[1], (-IVec3::X, s.blocks[1].1));
+}
+#[test]
+fn structure_placement_test() {
+    let house = Structure::house();
+    let mut world = World::new();
+    house
+        .place(&mut world, ivec3(10, 10, 5), Rotation::Half)
+        .unwrap();
+    // the door was towards -y, after half a turn it's towards +y
+    assert_eq!(world.get_block(ivec3(10, 10, 5)), AIR);
+    assert_eq!(world.get_block(ivec3(10, 10, 4)), TILE);
+    assert_eq!(world.get_block(ivec3(11, 10, 5)), SMOOTH_STONE);
+    let machine = world.get_state(ivec3(10, 7, 5));
+    assert_eq!(machine.id, MACHINE);
+    assert_eq!(machine.facing(), Facing::South);
+
+    // placing chunk by chunk gives the same blocks as placing it at once
+    let tree = Structure::tree();
+    let origin = ivec3(15, 0, 3);
+    let mut whole = World::new();
+    tree.place(&mut whole, origin, Rotation::None).unwrap();
+    let mut parts = World::new();
+    for c in chunks_in_reach(ivec2(0, 0), tree.reach()) {
+        tree.place_in_chunk(&mut parts, origin, Rotation::None, c);
+    }
+    let mut a: Vec<_> = whole.blocks().collect();
+    let mut b: Vec<_> = parts.blocks().collect();
+    a.sort_by_key(|(p, _)| p.to_array());
+    b.sort_by_key(|(p, _)| p.to_array());
+    assert_eq!(a, b);
+}
@@ -0,0 +1,206 @@
+/****************************************
+   Block Updates
+   the world moves forward in fixed ticks, on
+   every tick blocks get updated in two ways:
+   scheduled updates a few ticks after something
+   next to them changed (sand falling, fluids
+   flowing) and random ticks hitting a few blocks
+   of every chunk (grass spreading, crops growing),
+   what a block does is up to it's registry entry
+****************************************/
+use macroquad::math::{ivec3, IVec2, IVec3};
+
+use crate::{
+    chunk::{world_of, CHUNK_SIZE, HEIGHT},
+    entity::{Entity, EntityKind},
+    noise::hash3,
+    registry::{AIR, DIRT, GRASS},
+    state::BlockState,
+    world::{World, NEIGHBORS},
+};
+
+/// ticks a block waits before it starts falling
+pub const FALL_DELAY: u64 = 2;
+/// blocks of every chunk hit by a random tick each tick
+const RANDOM_TICKS: u32 = 12;
+/// the least light grass needs to spread and crops need to grow
+const GROW_LIGHT: u8 = 9;
+
+impl World {
+    /// updates the block at `pos` once `delay` more ticks ran (at least 1),
+    /// a block is only updated once per tick no matter how often it's scheduled
+    pub fn schedule_update(&mut self, pos: IVec3, delay: u64) {
+        self.scheduled
+            .insert((self.time + delay.max(1), pos.to_array()));
+    }
+    /// schedules the blocks a change at `pos` can matter to,
+    /// that's the block itself and the ones sharing a face with it
+    pub(crate) fn schedule_around(&mut self, pos: IVec3) {
+        for p in NEIGHBORS.iter().map(|d| pos + *d).chain([pos]) {
+            let def = self.registry().get(self.get_block(p));
+            if def.on_update.is_some() {
+                let delay = def.update_delay;
+                self.schedule_update(p, delay);
+            }
+        }
+    }
+    /// schedules every block of a chunk that was just put into the world and the
+    /// blocks around it, fluid held back by the chunk not being loaded flows on
+    pub(crate) fn schedule_chunk(&mut self, chunk: IVec2) {
+        let border = (0..HEIGHT).flat_map(|z| {
+            (0..CHUNK_SIZE).flat_map(move |i| {
+                [(-1, i), (CHUNK_SIZE, i), (i, -1), (i, CHUNK_SIZE)]
+                    .map(|(x, y)| world_of(chunk, ivec3(x, y, z)))
+            })
+        });
+        let updates: Vec<(IVec3, u64)> = self
+            .chunk_blocks(chunk)
+            .map(|(p, _)| p)
+            .chain(border)
+            .map(|p| (p, self.registry().get(self.get_block(p))))
+            .filter(|(_, def)| def.on_update.is_some())
+            .map(|(p, def)| (p, def.update_delay))
+            .collect();
+        for (pos, delay) in updates {
+            self.schedule_update(pos, delay);
+        }
+    }
+    /// advances the world by a tick running every update that's due,
+    /// the random ticks and the block entities
+    pub fn tick(&mut self) {
+        self.time += 1;
+        while let Some((tick, pos)) = self.scheduled.first().copied() {
+            if tick > self.time {
+                break;
+            }
+            self.scheduled.pop_first();
+            let pos = IVec3::from_array(pos);
+            let state = self.get_state(pos);
+            if let Some(update) = self.registry().get(state.id).on_update {
+                update(self, pos, state);
+            }
+        }
+        self.random_ticks();
+        self.tick_block_entities();
+    }
+    /// hits a few random blocks of every chunk, the same tick always hits the same blocks
+    fn random_ticks(&mut self) {
+        let chunks: Vec<IVec2> = self
+            .chunks()
+            .filter(|(_, c)| !c.is_empty())
+            .map(|(c, _)| c)
+            .collect();
+        for chunk in chunks {
+            for i in 0..RANDOM_TICKS {
+                let roll = hash3(self.time, chunk.x, chunk.y, i as i32);
+                let local = ivec3(
+                    (roll % CHUNK_SIZE as u64) as i32,
+                    ((roll >> 8) % CHUNK_SIZE as u64) as i32,
+                    ((roll >> 16) % HEIGHT as u64) as i32,
+                );
+                let pos = world_of(chunk, local);
+                let state = self.get_state(pos);
+                if let Some(tick) = self.registry().get(state.id).on_random_tick {
+                    tick(self, pos, state, roll >> 32);
+                }
+            }
+        }
+    }
+    /// whether the block above lets enough light through for plants
+    fn lit_for_plants(&self, pos: IVec3) -> bool {
+        let above = pos + IVec3::Z;
+        self.registry().is_transparent(self.get_block(above)) && self.light_at(above) >= GROW_LIGHT
+    }
+}
+
+/// gravity blocks fall as entities once nothing solid is under them
+pub fn fall(world: &mut World, pos: IVec3, state: BlockState) {
+    let supported = pos.z == 0 || world.registry().is_solid(world.get_block(pos - IVec3::Z));
+    // the block above gets scheduled by this so whole columns come down
+    if !supported && world.set_state(pos, AIR.into()).is_ok() {
+        world.spawn(Entity::new(pos.as_vec3(), EntityKind::FallingBlock(state)));
+    }
+}
+/// grass dies once it's covered and spreads onto lit dirt close to it
+pub fn spread_grass(world: &mut World, pos: IVec3, _state: BlockState, roll: u64) {
+    if !world
+        .registry()
+        .is_transparent(world.get_block(pos + IVec3::Z))
+    {
+        world.set_block(pos, DIRT).unwrap();
+        return;
+    }
+    if !world.lit_for_plants(pos) {
+        return;
+    }
+    // anywhere a block to the sides, from one up to three down
+    let target = pos
+        + ivec3(
+            (roll % 3) as i32 - 1,
+            (roll / 3 % 3) as i32 - 1,
+            (roll / 9 % 5) as i32 - 3,
+        );
+    if world.get_block(target) == DIRT && world.lit_for_plants(target) {
+        world.set_block(target, GRASS).unwrap();
+    }
+}
+/// crops grow a stage every few random ticks while they get enough light
+pub fn grow_crop(world: &mut World, pos: IVec3, state: BlockState, roll: u64) {
+    if state.stage() < 7 && world.light_at(pos) >= GROW_LIGHT && roll.is_multiple_of(3) {
+        world
+            .set_state(pos, state.with_stage(state.stage() + 1))
+            .unwrap();
+    }
+}
+#[test]
+fn gravity_test() {
+    use crate::registry::{SAND, STONE};
+    let mut world = World::new();
+    world.set_block(ivec3(0, 0, 0), STONE).unwrap();
+    world.fill(ivec3(0, 0, 3), ivec3(0, 0, 5), SAND).unwrap();
+    world.set_block(ivec3(0, 0, 2), STONE).unwrap();
+    // supported sand stays where it is
+    for _ in 0..10 {
+        world.tick();
+    }
+    assert!(world.entities().is_empty());
+    // and comes down as a whole column once the support is gone
+    world.set_block(ivec3(0, 0, 2), AIR).unwrap();
+    for _ in 0..20 {
+        world.tick();
+        world.step_entities(0.05);
+    }
+    assert!(world.entities().is_empty());
+    let column: Vec<_> = world
+        .column(0, 0)
+        .filter(|(_, s)| s.id != AIR)
+        .map(|(p, s)| (p.z, s.id))
+        .collect();
+    assert_eq!(column, [(0, STONE), (1, SAND), (2, SAND), (3, SAND)]);
+}
+#[test]
+fn random_tick_test() {
+    use crate::registry::{STONE, WHEAT};
+    let mut world = World::new();
+    world.fill(ivec3(0, 0, 0), ivec3(15, 15, 0), DIRT).unwrap();
+    world.set_block(ivec3(3, 3, 1), WHEAT).unwrap();
+    world.enable_lighting();
+    // random ticks find the crop sooner or later
+    for _ in 0..60_000 {
+        world.tick();
+    }
+    assert_eq!(world.get_state(ivec3(3, 3, 1)).stage(), 7);
+    // grass spreads onto lit dirt, the roll picks where (here one block towards +x)
+    let grass = ivec3(8, 8, 0);
+    world.set_block(grass, GRASS).unwrap();
+    spread_grass(&mut world, grass, GRASS.into(), 2 + 3 + 27);
+    assert_eq!(world.get_block(grass + IVec3::X), GRASS);
+    // but not under a block (one towards +y)
+    world.set_block(grass + ivec3(0, 1, 1), STONE).unwrap();
+    spread_grass(&mut world, grass, GRASS.into(), 1 + 6 + 27);
+    assert_eq!(world.get_block(grass + IVec3::Y), DIRT);
+    // and dies when it's covered
+    world.set_block(grass + IVec3::Z, STONE).unwrap();
+    spread_grass(&mut world, grass, GRASS.into(), 0);
+    assert_eq!(world.get_block(grass), DIRT);
+}
@@ -1,53 +1,512 @@
-use macroquad::math::{vec3, Vec3};
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fmt,
+};
 
-const WIDTH: usize = 256;
-const HEIGHT: usize = 64;
-const AREA: usize = WIDTH * WIDTH;
-const VOL: usize = AREA * HEIGHT;
+#[cfg(test)]
+use macroquad::math::vec3;
+use macroquad::math::{ivec2, ivec3, IVec2, IVec3, Vec3};
+
+use crate::{
+    biome::Biome,
+    block_entity::BlockEntity,
+    chunk::{chunk_of, local_of, world_of, Chunk, ColumnTop, CHUNK_SIZE, HEIGHT},
+    entity::Entity,
+    registry::{BlockId, BlockRegistry, AIR},
+    state::BlockState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorldError {
+    /// tried to write a block outside of the world's height limit
+    OutOfBounds(IVec3),
+    /// tried to write a block into a chunk of a streamed world that isn't loaded
+    NotLoaded(IVec2),
+}
+impl fmt::Display for WorldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorldError::OutOfBounds(pos) => {
+                write!(f, "{pos} is outside of the world (height 0..{HEIGHT})")
+            }
+            WorldError::NotLoaded(chunk) => write!(f, "chunk {chunk} isn't loaded"),
+        }
+    }
+}
+impl Error for WorldError {}
+/// a block that was set to a different state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockChange {
+    pub pos: IVec3,
+    pub old: BlockState,
+    pub new: BlockState,
+}
 /// world only stores tiles as they can be only one tile per block
+///
+/// tiles are kept in chunks keyed by their (signed) chunk coordinates,
+/// chunks are only allocated once a block is placed inside of them
 pub struct World {
-    tile_storage: Vec<Vec<Vec<u8>>>,
-    // entity_storage
+    chunks: HashMap<IVec2, Chunk>,
+    registry: BlockRegistry,
+    /// changes since they were last taken, `None` while nobody tracks them
+    changes: Option<Vec<BlockChange>>,
+    /// positions whose light changed, recorded along with the changes
+    light_changes: Vec<IVec3>,
+    /// whether light is kept up to date on every change, see [`World::enable_lighting`]
+    lighting: bool,
+    /// ticks run so far, see [`crate::update`]
+    pub(crate) time: u64,
+    /// block updates by the tick they're due
+    pub(crate) scheduled: BTreeSet<(u64, [i32; 3])>,
+    /// everything that isn't a block, see [`crate::entity`]
+    pub(crate) entities: Vec<Entity>,
+    /// data owned by blocks by their position, see [`crate::block_entity`]
+    pub(crate) block_entities: HashMap<IVec3, BlockEntity>,
+    /// set while a power network writes it's signals, see [`crate::power`]
+    pub(crate) powering: bool,
+    /// seed the world was generated with, kept so chunks generated later fit in
+    seed: u64,
+    /// whether chunks come and go with the player, see [`World::set_streamed`]
+    streamed: bool,
 }
 impl World {
     pub fn new() -> Self {
         Self {
-            tile_storage: vec![vec![vec![0; WIDTH]; WIDTH]; HEIGHT],
+            chunks: HashMap::new(),
+            registry: BlockRegistry::default(),
+            changes: None,
+            light_changes: Vec::new(),
+            lighting: false,
+            time: 0,
+            scheduled: BTreeSet::new(),
+            entities: Vec::new(),
+            block_entities: HashMap::new(),
+            powering: false,
+            seed: 0,
+            streamed: false,
         }
     }
-    pub fn set_block(&mut self, x: usize, y: usize, z: usize, b: u8) {
-        self.tile_storage[z][y][x] = b;
+    /// the blocks this world's ids refer to
+    pub fn registry(&self) -> &BlockRegistry {
+        &self.registry
+    }
+    /// whether a block position lies inside the world's height limit
+    #[inline]
+    pub fn in_bounds(pos: IVec3) -> bool {
+        (0..HEIGHT).contains(&pos.z)
+    }
+    /// state of the block at a position, `None` if the position is outside of the world
+    pub fn try_get_state(&self, pos: IVec3) -> Option<BlockState> {
+        if !Self::in_bounds(pos) {
+            return None;
+        }
+        Some(
+            self.chunks
+                .get(&chunk_of(pos))
+                .map_or(BlockState::AIR, |c| c.get(local_of(pos))),
+        )
+    }
+    /// state of the block at a position, anything outside of the world reads as air
+    pub fn get_state(&self, pos: IVec3) -> BlockState {
+        self.try_get_state(pos).unwrap_or(BlockState::AIR)
+    }
+    /// block at a position, `None` if the position is outside of the world
+    pub fn try_get_block(&self, pos: IVec3) -> Option<BlockId> {
+        self.try_get_state(pos).map(|s| s.id)
+    }
+    /// block at a position, anything outside of the world reads as air
+    pub fn get_block(&self, pos: IVec3) -> BlockId {
+        self.try_get_block(pos).unwrap_or(AIR)
     }
-    pub fn get_block(&self, x: usize, y: usize, z: usize) -> u8 {
-        self.tile_storage[z][y][x]
+    /// block at the cell holding a point in space, anything outside of the world reads as air
+    pub fn get_block_f(&self, pos: Vec3) -> BlockId {
+        self.get_block(pos.floor().as_ivec3())
     }
-    pub fn get_block_f(&self, pos: Vec3) -> u8 {
-        let mut pos = pos;
-        if pos.x.is_sign_negative() {
-            pos.x = -pos.x;
+    pub fn set_state(&mut self, pos: IVec3, b: BlockState) -> Result<(), WorldError> {
+        if !Self::in_bounds(pos) {
+            return Err(WorldError::OutOfBounds(pos));
         }
-        if pos.y.is_sign_negative() {
-            pos.y = -pos.y;
+        let key = chunk_of(pos);
+        if !self.chunks.contains_key(&key) {
+            if self.streamed {
+                return Err(WorldError::NotLoaded(key));
+            }
+            // a chunk that was never created is all air already
+            if b.id == AIR {
+                return Ok(());
+            }
+        }
+        let created = !self.chunks.contains_key(&key);
+        let registry = &self.registry;
+        let chunk = self.chunks.entry(key).or_default();
+        let local = local_of(pos);
+        let old = chunk.get(local);
+        chunk.set(local, b);
+        let top = chunk.top(local.x, local.y);
+        let top = ColumnTop {
+            solid: column_top(top.solid, pos.z, |z| {
+                registry.is_solid(chunk.get(local.with_z(z)).id)
+            }),
+            opaque: column_top(top.opaque, pos.z, |z| {
+                !registry.is_transparent(chunk.get(local.with_z(z)).id)
+            }),
+        };
+        chunk.set_top(local.x, local.y, top);
+        if let Some(changes) = self.changes.as_mut().filter(|_| old != b) {
+            changes.push(BlockChange { pos, old, new: b });
+        }
+        if self.lighting && created {
+            self.relight_chunk(key);
+        } else if self.lighting && old != b {
+            self.update_light(pos, old, b);
+        }
+        if old != b {
+            self.replace_block_entity(pos, old, b);
+            self.update_power(pos, old, b);
+            self.schedule_around(pos);
+        }
+        Ok(())
+    }
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+    /// marks the world as only holding the chunks around the player, setting
+    /// blocks in chunks that aren't loaded fails then instead of creating them
+    pub fn set_streamed(&mut self, streamed: bool) {
+        self.streamed = streamed;
+    }
+    /// whether blocks of a chunk can be set, a world that isn't streamed creates
+    /// it's chunks when they're needed so there all of them can
+    pub fn is_loaded(&self, chunk: IVec2) -> bool {
+        !self.streamed || self.chunks.contains_key(&chunk)
+    }
+    /// lights up the whole world and keeps the light up to date from now on,
+    /// lighting every change while generating is slow so it's best done once after
+    pub fn enable_lighting(&mut self) {
+        self.lighting = true;
+        self.relight();
+    }
+    /// starts or stops recording every block change, generating a world is
+    /// a lot of changes so it's best to only start tracking after it's done
+    pub fn track_changes(&mut self, track: bool) {
+        self.changes = track.then(Vec::new);
+        self.light_changes.clear();
+    }
+    pub fn is_tracking_changes(&self) -> bool {
+        self.changes.is_some()
+    }
+    /// the changes recorded so far without taking them, oldest first
+    pub fn pending_changes(&self) -> &[BlockChange] {
+        self.changes.as_deref().unwrap_or_default()
+    }
+    /// the changes recorded since the last call, oldest first
+    pub fn take_changes(&mut self) -> Vec<BlockChange> {
+        self.changes
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+    /// the positions whose light changed since the last call, recorded while changes are tracked
+    pub fn take_light_changes(&mut self) -> Vec<IVec3> {
+        std::mem::take(&mut self.light_changes)
+    }
+    pub(crate) fn light_changed(&mut self, pos: IVec3) {
+        if self.changes.is_some() {
+            self.light_changes.push(pos);
         }
-        if pos.z.is_sign_negative() {
-            pos.z = -pos.z;
+    }
+    /// places a block in it's default state
+    pub fn set_block(&mut self, pos: IVec3, b: BlockId) -> Result<(), WorldError> {
+        self.set_state(pos, BlockState::new(b))
+    }
+    /// biome of a column, columns that were never generated are plains
+    pub fn biome_at(&self, x: i32, y: i32) -> Biome {
+        let pos = ivec3(x, y, 0);
+        let local = local_of(pos);
+        self.chunks
+            .get(&chunk_of(pos))
+            .map_or(Biome::default(), |c| c.biome(local.x, local.y))
+    }
+    /// sets the biome of a column, like blocks it can't be set in a chunk that isn't loaded
+    pub fn set_biome(&mut self, x: i32, y: i32, biome: Biome) -> Result<(), WorldError> {
+        let pos = ivec3(x, y, 0);
+        let (key, local) = (chunk_of(pos), local_of(pos));
+        if !self.is_loaded(key) {
+            return Err(WorldError::NotLoaded(key));
         }
-        self.tile_storage[pos.z.floor() as usize][pos.y.floor() as usize][pos.x.floor() as usize]
+        self.chunks
+            .entry(key)
+            .or_default()
+            .set_biome(local.x, local.y, biome);
+        Ok(())
+    }
+    /// whether a block can be seen from the camera, only the top and the
+    /// two faces towards +x and +y are ever visible
+    pub fn is_exposed(&self, pos: IVec3) -> bool {
+        [IVec3::X, IVec3::Y, IVec3::Z]
+            .iter()
+            .any(|d| self.registry.is_transparent(self.get_block(pos + *d)))
     }
-    pub fn blocks(&self) -> Vec<(Vec3, u8)> {
-        let mut dest: Vec<(Vec3, u8)> = Vec::new();
-        for (i, e) in self.tile_storage.iter().enumerate() {
-            let z = i;
-            for (i, e) in e.iter().enumerate() {
-                let y = i;
-                for (i, e) in e.iter().enumerate() {
-                    let x = i;
-                    if *e != 0 {
-                        dest.push((vec3(x as f32, y as f32, z as f32), *e));
-                    }
-                }
+    /// every allocated chunk with it's chunk coordinates
+    pub fn chunks(&self) -> impl Iterator<Item = (IVec2, &Chunk)> {
+        self.chunks.iter().map(|(k, c)| (*k, c))
+    }
+    /// highest solid and opaque blocks of a column, both `None` for empty columns
+    pub fn top_block(&self, x: i32, y: i32) -> ColumnTop {
+        let pos = ivec3(x, y, 0);
+        let local = local_of(pos);
+        self.chunks
+            .get(&chunk_of(pos))
+            .map_or(ColumnTop::default(), |c| c.top(local.x, local.y))
+    }
+    /// puts a whole chunk into the world replacing whatever was there
+    pub fn insert_chunk(&mut self, pos: IVec2, mut chunk: Chunk) {
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let block = |z: i32| chunk.get(ivec3(x, y, z)).id;
+                let top = ColumnTop {
+                    solid: (0..HEIGHT)
+                        .rev()
+                        .find(|z| self.registry.is_solid(block(*z))),
+                    opaque: (0..HEIGHT)
+                        .rev()
+                        .find(|z| !self.registry.is_transparent(block(*z))),
+                };
+                chunk.set_top(x, y, top);
             }
         }
-        dest
+        self.chunks.insert(pos, chunk);
+        if self.lighting {
+            self.relight_chunk(pos);
+        }
+        self.create_chunk_block_entities(pos);
+        self.schedule_chunk(pos);
+    }
+    /// takes a chunk out of the world together with the entities of it's blocks,
+    /// the updates scheduled in it are dropped
+    pub fn remove_chunk(&mut self, pos: IVec2) -> Option<(Chunk, Vec<(IVec3, BlockEntity)>)> {
+        let chunk = self.chunks.remove(&pos)?;
+        let entities: Vec<(IVec3, BlockEntity)> = self
+            .block_entities
+            .extract_if(|p, _| chunk_of(*p) == pos)
+            .collect();
+        self.scheduled
+            .retain(|(_, p)| chunk_of(IVec3::from_array(*p)) != pos);
+        Some((chunk, entities))
+    }
+    pub(crate) fn chunk(&self, pos: IVec2) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+    pub(crate) fn chunk_mut(&mut self, pos: IVec2) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&pos)
+    }
+    /// every non air block of the world
+    pub fn blocks(&self) -> impl Iterator<Item = (IVec3, BlockState)> + '_ {
+        self.chunks.keys().flat_map(|c| self.chunk_blocks(*c))
+    }
+    /// every non air block of the chunk at chunk coordinates `chunk`
+    pub fn chunk_blocks(&self, chunk: IVec2) -> impl Iterator<Item = (IVec3, BlockState)> + '_ {
+        self.chunks
+            .get(&chunk)
+            .into_iter()
+            .flat_map(move |c| c.blocks().map(move |(l, b)| (world_of(chunk, l), b)))
+    }
+    /// every non air block in the box between two corners (inclusive),
+    /// only chunks overlapping the box are looked at
+    pub fn blocks_in(
+        &self,
+        from: IVec3,
+        to: IVec3,
+    ) -> impl Iterator<Item = (IVec3, BlockState)> + '_ {
+        let min = from.min(to).with_z(from.z.min(to.z).max(0));
+        let max = from.max(to).with_z(from.z.max(to.z).min(HEIGHT - 1));
+        let (cmin, cmax) = (chunk_of(min), chunk_of(max));
+        (cmin.y..=cmax.y)
+            .flat_map(move |cy| (cmin.x..=cmax.x).map(move |cx| ivec2(cx, cy)))
+            .filter_map(|c| self.chunks.get(&c).map(|chunk| (c, chunk)))
+            .flat_map(move |(c, chunk)| {
+                let origin = world_of(c, IVec3::ZERO);
+                let lo = (min - origin).max(IVec3::ZERO);
+                let hi = (max - origin).min(ivec3(CHUNK_SIZE - 1, CHUNK_SIZE - 1, HEIGHT - 1));
+                (lo.z..=hi.z)
+                    .flat_map(move |z| {
+                        (lo.y..=hi.y).flat_map(move |y| (lo.x..=hi.x).map(move |x| ivec3(x, y, z)))
+                    })
+                    .map(move |l| (origin + l, chunk.get(l)))
+            })
+            .filter(|(_, b)| b.id != AIR)
+    }
+    /// the (up to) 6 blocks sharing a face with a position, air included
+    #[allow(dead_code)]
+    pub fn neighbors(&self, pos: IVec3) -> impl Iterator<Item = (IVec3, BlockState)> + '_ {
+        NEIGHBORS
+            .iter()
+            .map(move |d| pos + *d)
+            .filter(|p| Self::in_bounds(*p))
+            .map(|p| (p, self.get_state(p)))
+    }
+    /// every cell of a column from the bottom of the world to the top, air included
+    #[allow(dead_code)]
+    pub fn column(&self, x: i32, y: i32) -> impl Iterator<Item = (IVec3, BlockState)> + '_ {
+        let chunk = self.chunks.get(&chunk_of(ivec3(x, y, 0)));
+        let local = local_of(ivec3(x, y, 0));
+        (0..HEIGHT).map(move |z| {
+            let state = chunk.map_or(BlockState::AIR, |c| c.get(local.with_z(z)));
+            (ivec3(x, y, z), state)
+        })
+    }
+}
+/// the new top of a column after the block at `z` changed, `is` tells if the block at a height counts
+fn column_top(top: Option<i32>, z: i32, is: impl Fn(i32) -> bool) -> Option<i32> {
+    if is(z) {
+        Some(top.map_or(z, |t| t.max(z)))
+    } else if top == Some(z) {
+        // the top was removed, look for the next one down
+        (0..z).rev().find(|z| is(*z))
+    } else {
+        top
+    }
+}
+/// offsets to the 6 blocks sharing a face with a block
+pub const NEIGHBORS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+#[test]
+fn chunked_world_test() {
+    let mut world = World::new();
+    assert!(world.blocks().next().is_none());
+    world.set_block(ivec3(-20, 5, 63), 3).unwrap();
+    world.set_block(ivec3(300, 300, 0), 1).unwrap();
+    assert_eq!(world.get_block(ivec3(-20, 5, 63)), 3);
+    assert_eq!(world.get_block(ivec3(300, 300, 0)), 1);
+    assert_eq!(world.get_block(ivec3(20, 5, 63)), 0);
+    assert_eq!(world.blocks().count(), 2);
+    world.set_block(ivec3(-20, 5, 63), 0).unwrap();
+    assert_eq!(world.blocks().count(), 1);
+    // clearing a block doesn't allocate a chunk
+    world.set_block(ivec3(1000, 5, 0), 0).unwrap();
+    assert_eq!(world.chunks.len(), 2);
+}
+#[test]
+fn world_bounds_test() {
+    let mut world = World::new();
+    world.set_block(ivec3(3, 0, 0), 2).unwrap();
+    // negative positions are their own cells, not a mirror of positive ones
+    assert_eq!(world.get_block_f(vec3(-2.5, 0.5, 0.5)), 0);
+    assert_eq!(world.get_block_f(vec3(3.5, 0.5, 0.5)), 2);
+    assert_eq!(world.try_get_block(ivec3(0, 0, -1)), None);
+    assert_eq!(world.get_block(ivec3(0, 0, HEIGHT)), 0);
+    assert_eq!(
+        world.set_block(ivec3(0, 0, HEIGHT), 1),
+        Err(WorldError::OutOfBounds(ivec3(0, 0, HEIGHT)))
+    );
+}
+#[test]
+fn change_tracking_test() {
+    let mut world = World::new();
+    world.set_block(ivec3(0, 0, 0), 1).unwrap();
+    world.track_changes(true);
+    assert!(world.take_changes().is_empty());
+    world.set_block(ivec3(0, 0, 0), 2).unwrap();
+    // setting the same state again isn't a change
+    world.set_block(ivec3(0, 0, 0), 2).unwrap();
+    world.set_block(ivec3(0, 0, 1), 3).unwrap();
+    let changes = world.take_changes();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(
+        changes[0],
+        BlockChange {
+            pos: ivec3(0, 0, 0),
+            old: BlockState::new(1),
+            new: BlockState::new(2),
+        }
+    );
+    assert!(world.take_changes().is_empty());
+    world.track_changes(false);
+    world.set_block(ivec3(0, 0, 2), 3).unwrap();
+    assert!(world.take_changes().is_empty());
+}
+#[test]
+fn world_queries_test() {
+    let mut world = World::new();
+    for p in [
+        ivec3(-1, -1, 0),
+        ivec3(0, 0, 0),
+        ivec3(15, 0, 5),
+        ivec3(16, 0, 5),
+        ivec3(3, 3, 63),
+    ] {
+        world.set_block(p, 1).unwrap();
     }
+    let mut region: Vec<IVec3> = world
+        .blocks_in(ivec3(16, 0, 5), ivec3(-1, -1, -10))
+        .map(|(p, _)| p)
+        .collect();
+    region.sort_by_key(|p| p.to_array());
+    assert_eq!(
+        region,
+        vec![
+            ivec3(-1, -1, 0),
+            ivec3(0, 0, 0),
+            ivec3(15, 0, 5),
+            ivec3(16, 0, 5)
+        ]
+    );
+    assert_eq!(world.chunk_blocks(ivec2(0, 0)).count(), 3);
+    assert_eq!(world.chunk_blocks(ivec2(7, 7)).count(), 0);
+    // the bottom of the world has no neighbour below
+    assert_eq!(world.neighbors(ivec3(0, 0, 0)).count(), 5);
+    assert_eq!(
+        world
+            .neighbors(ivec3(-1, 0, 0))
+            .filter(|(_, b)| b.id == 1)
+            .count(),
+        2
+    );
+    let column: Vec<_> = world.column(3, 3).collect();
+    assert_eq!(column.len(), HEIGHT as usize);
+    assert_eq!(column[63], (ivec3(3, 3, 63), BlockState::new(1)));
+}
+#[test]
+fn heightmap_test() {
+    use crate::registry::{LEAVES, STONE};
+    let mut world = World::new();
+    assert_eq!(world.top_block(4, 4), ColumnTop::default());
+    world.set_block(ivec3(4, 4, 2), STONE).unwrap();
+    world.set_block(ivec3(4, 4, 10), LEAVES).unwrap();
+    world.set_block(ivec3(4, 4, 5), STONE).unwrap();
+    let top = world.top_block(4, 4);
+    assert_eq!((top.solid, top.opaque), (Some(10), Some(5)));
+    world.set_block(ivec3(4, 4, 10), AIR).unwrap();
+    world.set_block(ivec3(4, 4, 5), AIR).unwrap();
+    let top = world.top_block(4, 4);
+    assert_eq!((top.solid, top.opaque), (Some(2), Some(2)));
+    world.set_block(ivec3(4, 4, 2), AIR).unwrap();
+    assert_eq!(world.top_block(4, 4), ColumnTop::default());
+    // chunks put in whole get their heightmap built
+    let mut states = vec![BlockState::AIR; Chunk::VOLUME];
+    states[7 * 256 + 16 + 1] = BlockState::new(STONE);
+    world.insert_chunk(ivec2(1, 0), Chunk::from_states(states));
+    assert_eq!(world.top_block(17, 1).solid, Some(7));
+}
+#[test]
+fn block_state_storage_test() {
+    use crate::state::Facing;
+    let mut world = World::new();
+    let machine = BlockState::new(7).with_facing(Facing::West);
+    world.set_state(ivec3(1, 2, 3), machine).unwrap();
+    assert_eq!(world.get_state(ivec3(1, 2, 3)), machine);
+    assert_eq!(world.get_block(ivec3(1, 2, 3)), 7);
+    // placing a bare id resets the state
+    world.set_block(ivec3(1, 2, 3), 7).unwrap();
+    assert_eq!(world.get_state(ivec3(1, 2, 3)).facing(), Facing::East);
 }
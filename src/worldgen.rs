@@ -0,0 +1,229 @@
+/****************************************
+   World Generation
+   generators fill the world chunk by chunk,
+   a chunk must only depend on the seed and
+   it's position so chunks can be generated
+   in any order and always come out the same
+****************************************/
+use macroquad::math::{ivec2, ivec3, IVec2, IVec3};
+
+use crate::{
+    biome::{Biome, BiomeSource},
+    carving::{CaveCarver, OreVein},
+    chunk::{CHUNK_SIZE, HEIGHT},
+    noise::{sub_seed, Noise},
+    registry::{BlockId, STONE},
+    structure::{chunks_in_reach, Decoration},
+    world::World,
+};
+
+pub trait WorldGenerator {
+    /// fills the chunk at chunk coordinates `chunk` of the world
+    fn generate_chunk(&self, world: &mut World, chunk: IVec2);
+    /// fills every chunk between two chunk coordinates (inclusive)
+    #[allow(dead_code)]
+    fn generate_area(&self, world: &mut World, from: IVec2, to: IVec2) {
+        for cy in from.y.min(to.y)..=from.y.max(to.y) {
+            for cx in from.x.min(to.x)..=from.x.max(to.x) {
+                self.generate_chunk(world, ivec2(cx, cy));
+            }
+        }
+    }
+}
+
+/// a flat plane of one block at `z = 0`
+#[allow(dead_code)]
+pub struct FlatGenerator {
+    pub block: BlockId,
+}
+impl WorldGenerator for FlatGenerator {
+    fn generate_chunk(&self, world: &mut World, chunk: IVec2) {
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let pos = ivec3(chunk.x * CHUNK_SIZE + x, chunk.y * CHUNK_SIZE + y, 0);
+                world.set_block(pos, self.block).unwrap();
+            }
+        }
+    }
+}
+
+/// heightmapped terrain out of layered noise, shaped and covered by biomes
+pub struct TerrainGenerator {
+    pub seed: u64,
+    /// height the terrain is centered around
+    pub base_height: i32,
+    /// size of hills in blocks
+    pub scale: f32,
+    /// how many blocks of the biome's subsurface sit between the surface and stone
+    pub soil_depth: i32,
+    pub biomes: BiomeSource,
+    /// hollows out caves below the surface, `None` keeps the ground solid
+    pub caves: Option<CaveCarver>,
+    /// veins scattered through the ground after caves are carved
+    pub ores: Vec<OreVein>,
+    /// structures placed on the surface last, they may reach into neighbouring chunks
+    pub decorations: Vec<Decoration>,
+    height_noise: Noise,
+    detail_noise: Noise,
+}
+impl TerrainGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            base_height: HEIGHT / 2,
+            scale: 48.,
+            soil_depth: 3,
+            biomes: BiomeSource::new(seed),
+            caves: Some(CaveCarver::new(seed)),
+            ores: OreVein::defaults(),
+            decorations: Decoration::defaults(),
+            height_noise: Noise::new(sub_seed(seed, 1)),
+            detail_noise: Noise::new(sub_seed(seed, 2)),
+        }
+    }
+    /// z of the top block of a column
+    pub fn height_at(&self, x: i32, y: i32) -> i32 {
+        let (fx, fy) = (x as f32 / self.scale, y as f32 / self.scale);
+        let n = self.height_noise.fbm2(fx, fy, 4);
+        // every biome shapes the terrain as much as it's present so borders have no cliffs
+        let h: f32 = Biome::ALL
+            .iter()
+            .zip(self.biomes.weights(x, y))
+            .map(|(b, w)| (b.height_offset() + n * b.amplitude()) * w)
+            .sum();
+        let h = h + self.detail_noise.get2(fx * 4., fy * 4.) * 1.5;
+        (self.base_height + h.round() as i32).clamp(1, HEIGHT - 1)
+    }
+    /// where a decoration try at a column ends up, `None` if it doesn't fit there
+    fn decoration_origin(&self, deco: &Decoration, column: IVec3) -> Option<IVec3> {
+        if !deco
+            .biomes
+            .contains(&self.biomes.biome_at(column.x, column.y))
+        {
+            return None;
+        }
+        let height = self.height_at(column.x, column.y);
+        if let Some(slope) = deco.max_slope {
+            // only look at the terrain, never at the world, so neighbours can be missing
+            let r = deco.structure.reach();
+            let flat = [ivec2(-r, -r), ivec2(r, -r), ivec2(-r, r), ivec2(r, r)]
+                .iter()
+                .all(|d| (self.height_at(column.x + d.x, column.y + d.y) - height).abs() <= slope);
+            if !flat {
+                return None;
+            }
+        }
+        Some(ivec3(column.x, column.y, height + 1))
+    }
+    /// block at height `z` of a column of `biome` whose top block is at `height`
+    pub fn block_at(&self, biome: Biome, height: i32, z: i32) -> BlockId {
+        if z == height {
+            biome.surface()
+        } else if z > height - self.soil_depth {
+            biome.subsurface()
+        } else {
+            STONE
+        }
+    }
+}
+impl WorldGenerator for TerrainGenerator {
+    fn generate_chunk(&self, world: &mut World, chunk: IVec2) {
+        for ly in 0..CHUNK_SIZE {
+            for lx in 0..CHUNK_SIZE {
+                let (x, y) = (chunk.x * CHUNK_SIZE + lx, chunk.y * CHUNK_SIZE + ly);
+                let height = self.height_at(x, y);
+                let biome = self.biomes.biome_at(x, y);
+                world.set_biome(x, y, biome).unwrap();
+                for z in 0..=height {
+                    let pos = ivec3(x, y, z);
+                    if self.caves.as_ref().is_some_and(|c| c.is_cave(pos, height)) {
+                        continue;
+                    }
+                    world
+                        .set_block(pos, self.block_at(biome, height, z))
+                        .unwrap();
+                }
+            }
+        }
+        for (i, ore) in self.ores.iter().enumerate() {
+            ore.scatter(self.seed, i as u64, world, chunk);
+        }
+        // structures anchored in nearby chunks are placed too but clipped to this chunk,
+        // every chunk places them in the same order so overlaps always resolve the same way
+        for (i, deco) in self.decorations.iter().enumerate() {
+            let seed = sub_seed(self.seed, 40 + i as u64);
+            for source in chunks_in_reach(chunk, deco.structure.reach()) {
+                for (column, rotation) in deco.tries(seed, source) {
+                    if let Some(origin) = self.decoration_origin(deco, column) {
+                        deco.structure
+                            .place_in_chunk(world, origin, rotation, chunk);
+                    }
+                }
+            }
+        }
+    }
+}
+#[test]
+fn terrain_is_deterministic_test() {
+    let mut a = World::new();
+    let mut b = World::new();
+    TerrainGenerator::new(7).generate_area(&mut a, ivec2(-1, -1), ivec2(0, 0));
+    TerrainGenerator::new(7).generate_chunk(&mut b, ivec2(-1, 0));
+    let other = TerrainGenerator::new(8);
+    let mut differs = false;
+    for y in 0..CHUNK_SIZE {
+        for x in -CHUNK_SIZE..0 {
+            for z in 0..HEIGHT {
+                let p = ivec3(x, y, z);
+                assert_eq!(a.get_state(p), b.get_state(p));
+            }
+            differs |= TerrainGenerator::new(7).height_at(x, y) != other.height_at(x, y);
+        }
+    }
+    assert!(differs);
+}
+#[test]
+fn terrain_columns_test() {
+    let mut gen = TerrainGenerator::new(1234);
+    gen.decorations.clear();
+    let mut world = World::new();
+    gen.generate_chunk(&mut world, ivec2(2, -3));
+    for (x, y) in [(32, -48), (40, -40), (47, -33)] {
+        let h = gen.height_at(x, y);
+        assert!((1..HEIGHT).contains(&h));
+        let biome = world.biome_at(x, y);
+        assert_eq!(biome, gen.biomes.biome_at(x, y));
+        assert_eq!(world.get_block(ivec3(x, y, h)), biome.surface());
+        assert_eq!(world.get_block(ivec3(x, y, h + 1)), 0);
+        assert_ne!(world.get_block(ivec3(x, y, 0)), 0);
+    }
+}
+#[test]
+fn decorations_cross_chunks_test() {
+    use crate::{biome::Biome, registry::LOG, structure::Structure};
+    let mut gen = TerrainGenerator::new(21);
+    gen.caves = None;
+    gen.decorations = vec![Decoration {
+        structure: Structure::tree(),
+        biomes: &Biome::ALL,
+        per_chunk: 6.,
+        max_slope: None,
+    }];
+    let mut a = World::new();
+    let mut b = World::new();
+    gen.generate_area(&mut a, ivec2(0, 0), ivec2(1, 1));
+    for c in [ivec2(1, 1), ivec2(0, 1), ivec2(1, 0), ivec2(0, 0)] {
+        gen.generate_chunk(&mut b, c);
+    }
+    let mut logs = 0;
+    for y in 0..CHUNK_SIZE * 2 {
+        for x in 0..CHUNK_SIZE * 2 {
+            for z in 0..HEIGHT {
+                let p = ivec3(x, y, z);
+                assert_eq!(a.get_state(p), b.get_state(p));
+                logs += (a.get_block(p) == LOG) as i32;
+            }
+        }
+    }
+    assert!(logs > 0);
+}